@@ -0,0 +1,104 @@
+//! Man pages built from the command line definitions, used by the build scripts of rwm and rwmc,
+//! and the command trees rwmc completes in its shell
+
+#[cfg(test)]
+mod tests;
+
+use std::iter;
+
+use structopt::clap::{App, AppSettings, ErrorKind};
+
+/// Render a man page with the help of the app and of every one of its subcommands
+pub fn man_page(app: &App, section: u8, about: &str) -> String {
+    let app = app
+        .clone()
+        .global_setting(AppSettings::ColorNever)
+        .set_term_width(80);
+    let name = app.get_name();
+
+    let mut page = format!(
+        ".TH {} {}\n.SH NAME\n{} \\- {}\n.SH DESCRIPTION\n",
+        name.to_uppercase(),
+        section,
+        name,
+        escape(about)
+    );
+    page.push_str(&preformatted(&help::<&str>(&app, &[])));
+
+    let mut commands = String::new();
+    subcommands(&app, &mut Vec::new(), &mut commands);
+    if !commands.is_empty() {
+        page.push_str(".SH COMMANDS\n");
+        page.push_str(&commands);
+    }
+    page
+}
+
+/// Render the help of every subcommand under the given path, recursively
+fn subcommands(root: &App, path: &mut Vec<String>, out: &mut String) {
+    for sub in subcommand_names(root, path) {
+        path.push(sub);
+        out.push_str(&format!(".SS {} {}\n", root.get_name(), path.join(" ")));
+        out.push_str(&preformatted(&help(root, path)));
+        subcommands(root, path, out);
+        path.pop();
+    }
+}
+
+/// The names of the subcommands of the subcommand in the given path, without `help`
+pub fn subcommand_names<S: AsRef<str>>(root: &App, path: &[S]) -> Vec<String> {
+    let mut app = root;
+    for name in path {
+        match find_subcommand(app, name.as_ref()) {
+            Some(sub) => app = sub,
+            None => return Vec::new(),
+        }
+    }
+    let mut names = app
+        .p
+        .subcommands
+        .iter()
+        .map(|sub| sub.get_name().to_string())
+        .filter(|name| name != "help")
+        .collect::<Vec<_>>();
+    // Sorted like in the help
+    names.sort();
+    names
+}
+
+/// Clap 2 only keeps the subcommands of an app in its hidden parser
+fn find_subcommand<'x, 'a, 'b>(app: &'x App<'a, 'b>, name: &str) -> Option<&'x App<'a, 'b>> {
+    app.p.subcommands.iter().find(|sub| sub.get_name() == name)
+}
+
+/// The help clap prints for the subcommand in the given path
+fn help<S: AsRef<str>>(root: &App, path: &[S]) -> String {
+    let args = iter::once(root.get_name())
+        .chain(path.iter().map(AsRef::as_ref))
+        .chain(iter::once("--help"));
+    match root.clone().get_matches_from_safe(args) {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => e.message,
+        _ => String::new(),
+    }
+}
+
+/// Keep the text as it is instead of letting roff fill the lines
+fn preformatted(text: &str) -> String {
+    let mut out = String::from(".nf\n");
+    for line in text.trim_end().lines() {
+        out.push_str(&escape(line));
+        out.push('\n');
+    }
+    out.push_str(".fi\n");
+    out
+}
+
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\e");
+    if line.starts_with('.') || line.starts_with('\'') {
+        // Otherwise it would be taken for a request
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
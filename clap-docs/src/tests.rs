@@ -0,0 +1,31 @@
+use structopt::{clap::App, StructOpt};
+
+use common::Command;
+
+use crate::{man_page, subcommand_names};
+
+#[test]
+fn test_man_page() {
+    let page = man_page(&Command::clap(), 1, "The client");
+    assert!(page.starts_with(".TH RWMC 1\n.SH NAME\nrwmc \\- The client\n"));
+    assert!(page.contains(".SS rwmc window send tag\n"));
+    assert!(!page.contains(".SS rwmc help"));
+
+    // Backslashes and dots at the start of lines mean something to roff
+    let app = App::new("test").after_help(".not a request");
+    let page = man_page(&app, 1, "a \\ b");
+    assert!(page.contains("test \\- a \\e b\n"));
+    assert!(page.contains("\n\\&.not a request\n"));
+}
+
+#[test]
+fn test_subcommand_names() {
+    let app = Command::clap();
+    let names = subcommand_names::<&str>(&app, &[]);
+    assert!(names.contains(&String::from("window")), "{:?}", names);
+    assert!(!names.contains(&String::from("help")));
+
+    let names = subcommand_names(&app, &["window", "send"]);
+    assert_eq!(names, ["monitor", "tag"]);
+    assert!(subcommand_names(&app, &["quit"]).is_empty());
+}
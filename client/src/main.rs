@@ -1,32 +1,122 @@
-use std::{io::Write, os::unix::net::UnixStream, path::Path};
+mod opt;
+mod shell;
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+};
 
 use {
-    anyhow::{bail, ensure, Context, Result},
+    anyhow::{Context, Result},
     structopt::StructOpt,
 };
 
-use common::{into_message, read_message, Command};
+use crate::opt::{ClientCommand, Opt};
+use common::{connect, into_message, read_message, socket_path, Command, Request, Response};
+
+/// Split the arguments on `;` and parse each part as a command
+fn parse_batch(args: &[String]) -> Result<Vec<Command>, structopt::clap::Error> {
+    args.split(|arg| arg == ";")
+        .filter(|cmd| !cmd.is_empty())
+        .map(|cmd| {
+            Command::from_iter_safe(std::iter::once("rwmc").chain(cmd.iter().map(String::as_str)))
+        })
+        .collect()
+}
+
+/// Send a single command as it is and more than one as a batch
+fn into_request(mut cmds: Vec<Command>) -> Option<Request> {
+    match cmds.len() {
+        0 => None,
+        1 => Some(Request::Command(cmds.remove(0))),
+        _ => Some(Request::Batch(cmds)),
+    }
+}
+
+/// Send the request and wait for the reply
+fn send(stream: &mut UnixStream, request: &Request) -> Result<Response> {
+    stream
+        .write_all(&into_message(request)?)
+        .context("Error sending the command")?;
+
+    let reply = read_message(stream)?;
+    serde_json::from_str(&reply).context("Wrong response format")
+}
+
+/// Print the response, returning false if it's an error
+fn print_response(reply: Response) -> Result<bool> {
+    match reply {
+        Response::Success => {}
+        // Strings are printed as they are so things like `config print` are readable
+        Response::Data(serde_json::Value::String(data)) => println!("{}", data),
+        Response::Data(data) => println!("{}", serde_json::to_string_pretty(&data)?),
+        Response::Error(e) => {
+            eprintln!("{}", e);
+            return Ok(false);
+        }
+        Response::Batch(replies) => {
+            let mut ok = true;
+            for reply in replies {
+                ok &= print_response(reply)?;
+            }
+            return Ok(ok);
+        }
+    }
+    Ok(true)
+}
 
 fn main() -> Result<()> {
-    let opts = Command::from_args();
-    let message = into_message(opts)?;
-
-    let socket = Path::new("/tmp/rwm.sock");
-    let mut stream = match UnixStream::connect(&socket) {
-        Ok(s) => s,
-        Err(_) => {
-            bail!("Error connecting to the socket");
+    // Clap would take the commands of a batch for a subcommand, so they're split off first
+    let mut args = std::env::args().collect::<Vec<_>>();
+    let batch = match args.iter().position(|arg| arg == "--") {
+        Some(index) => args.split_off(index + 1),
+        None => Vec::new(),
+    };
+    let opts = Opt::from_iter(args.iter().filter(|arg| *arg != "--"));
+
+    let cmds = parse_batch(&batch).unwrap_or_else(|e| e.exit());
+    let request = match opts.cmd {
+        Some(ClientCommand::Completions { shell }) => {
+            Opt::clap().gen_completions_to("rwmc", shell, &mut io::stdout());
+            return Ok(());
+        }
+        Some(ClientCommand::Shell) => {
+            let stream = connect(&socket_path(opts.socket))?;
+            if !shell::run(stream)? {
+                std::process::exit(1);
+            }
+            return Ok(());
         }
+        Some(ClientCommand::Wm(cmd)) if cmds.is_empty() => Request::Command(cmd),
+        Some(_) => {
+            structopt::clap::Error::with_description(
+                "A command can't be given both before and after `--`",
+                structopt::clap::ErrorKind::ArgumentConflict,
+            )
+            .exit();
+        }
+        None => match into_request(cmds) {
+            Some(request) => request,
+            None => {
+                Opt::clap().print_help()?;
+                println!();
+                std::process::exit(1);
+            }
+        },
     };
+    let subscribe = matches!(request, Request::Command(Command::Subscribe { .. }));
 
-    ensure!(
-        stream.write(message.as_ref()).is_ok(),
-        "Error sending the command"
-    );
+    let mut stream = connect(&socket_path(opts.socket))?;
+    if !print_response(send(&mut stream, &request)?)? {
+        std::process::exit(1);
+    }
 
-    let reply = read_message(&mut stream)?;
-    let reply: String = serde_json::from_str(&reply).context("Wrong response format")?;
-    println!("{}", reply);
+    if subscribe {
+        // Events are sent as lines of JSON until the wm goes away
+        for line in BufReader::new(stream).lines() {
+            println!("{}", line.context("Failed to read event")?);
+        }
+    }
 
     Ok(())
 }
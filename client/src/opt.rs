@@ -0,0 +1,35 @@
+// The command line options, also included by build.rs to generate the man page
+
+use std::path::PathBuf;
+
+use structopt::{clap::Shell, StructOpt};
+
+use common::Command;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "rwmc",
+    about = "The rwm client",
+    after_help = "Several commands can be sent together after `--`, separated by `;`, \
+                  ex `rwmc -- tag switch 3 \\; layout next`"
+)]
+pub(crate) struct Opt {
+    /// Path to the socket of the wm, defaults to $XDG_RUNTIME_DIR/rwm-<display>.sock
+    #[structopt(long, env = "RWM_SOCKET", hide_env_values = true)]
+    pub(crate) socket: Option<PathBuf>,
+    #[structopt(subcommand)]
+    pub(crate) cmd: Option<ClientCommand>,
+}
+
+#[derive(StructOpt, Debug)]
+pub(crate) enum ClientCommand {
+    /// Print the completions of rwmc for the given shell
+    Completions {
+        #[structopt(possible_values = &Shell::variants())]
+        shell: Shell,
+    },
+    /// Read commands line by line from stdin over a single connection, printing every reply as JSON
+    Shell,
+    #[structopt(flatten)]
+    Wm(Command),
+}
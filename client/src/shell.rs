@@ -0,0 +1,179 @@
+//! `rwmc shell`, running commands from stdin over a single connection
+
+use std::{
+    io::{self, BufRead, IsTerminal},
+    os::unix::net::UnixStream,
+};
+
+use {
+    anyhow::{Context, Result},
+    rustyline::{
+        completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+        validate::Validator, Editor, Helper,
+    },
+    structopt::{
+        clap::{App, ErrorKind},
+        StructOpt,
+    },
+};
+
+use crate::{into_request, parse_batch, send};
+use common::{Command, Request, Response};
+
+/// Run every line of stdin as a command, printing the replies as JSON.
+/// Returns false if any command failed
+pub(crate) fn run(mut stream: UnixStream) -> Result<bool> {
+    let mut ok = true;
+    if io::stdin().is_terminal() {
+        let mut editor = Editor::new().context("Failed to set up the terminal")?;
+        editor.set_helper(Some(ShellHelper::new(&Command::clap())));
+        loop {
+            let line = match editor.readline("rwmc> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                Err(e) => return Err(e).context("Failed to read the command"),
+            };
+            editor.add_history_entry(line.as_str());
+            ok &= run_line(&mut stream, &line)?;
+        }
+    } else {
+        for line in io::stdin().lock().lines() {
+            ok &= run_line(&mut stream, &line.context("Failed to read the command")?)?;
+        }
+    }
+    Ok(ok)
+}
+
+/// Run the command(s) in the line, returning false if they failed
+fn run_line(stream: &mut UnixStream, line: &str) -> Result<bool> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(true);
+    }
+
+    let request = match parse_line(line) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(true),
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(false);
+        }
+    };
+
+    let reply = send(stream, &request)?;
+    println!("{}", serde_json::to_string(&reply)?);
+    Ok(!has_errors(&reply))
+}
+
+fn parse_line(line: &str) -> Result<Option<Request>, String> {
+    let args = shell_words::split(line).map_err(|e| e.to_string())?;
+    let cmds = match parse_batch(&args) {
+        Ok(cmds) => cmds,
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => {
+            println!("{}", e.message);
+            return Ok(None);
+        }
+        Err(e) => return Err(e.message),
+    };
+    if cmds
+        .iter()
+        .any(|cmd| matches!(cmd, Command::Subscribe { .. }))
+    {
+        return Err(String::from("Can't subscribe from the shell"));
+    }
+    Ok(into_request(cmds))
+}
+
+fn has_errors(reply: &Response) -> bool {
+    match reply {
+        Response::Error(_) => true,
+        Response::Batch(replies) => replies.iter().any(has_errors),
+        _ => false,
+    }
+}
+
+/// The names of the subcommands, used for completion
+struct CommandTree {
+    name: String,
+    subcommands: Vec<CommandTree>,
+}
+
+impl CommandTree {
+    fn new(root: &App, path: &mut Vec<String>) -> Self {
+        let subcommands = clap_docs::subcommand_names(root, path)
+            .into_iter()
+            .map(|name| {
+                path.push(name);
+                let sub = Self::new(root, path);
+                path.pop();
+                sub
+            })
+            .collect();
+        Self {
+            name: path
+                .last()
+                .map_or_else(|| root.get_name().to_string(), String::clone),
+            subcommands,
+        }
+    }
+}
+
+struct ShellHelper {
+    commands: CommandTree,
+}
+
+impl ShellHelper {
+    fn new(app: &App) -> Self {
+        Self {
+            commands: CommandTree::new(app, &mut Vec::new()),
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let prefix = &line[start..];
+
+        // Only the words after the last `;` are part of the current command
+        let words = line[..start].split_whitespace().collect::<Vec<_>>();
+        let words = match words.iter().rposition(|word| *word == ";") {
+            Some(i) => &words[i + 1..],
+            None => &words[..],
+        };
+
+        let mut node = &self.commands;
+        for word in words {
+            match node.subcommands.iter().find(|sub| sub.name == *word) {
+                Some(sub) => node = sub,
+                None => return Ok((start, Vec::new())),
+            }
+        }
+
+        let candidates = node
+            .subcommands
+            .iter()
+            .filter(|sub| sub.name.starts_with(prefix))
+            .map(|sub| format!("{} ", sub.name))
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
@@ -1,10 +1,12 @@
-use std::path::PathBuf;
+use std::{ffi::OsStr, fmt, path::PathBuf, str::FromStr};
 
 use {
     serde::{Deserialize, Serialize},
     structopt::StructOpt,
 };
 
+use crate::ParseConfigKeyError;
+
 #[derive(Deserialize, Serialize, StructOpt, Debug)]
 pub enum ConfigSubcommand {
     /// Print the current configuration
@@ -12,6 +14,76 @@ pub enum ConfigSubcommand {
     /// (Re)load config from a file
     Load {
         /// Path to the configuration file. If left empty it will try to reload the last file used.
+        #[structopt(parse(from_os_str = absolute_path))]
         path: Option<PathBuf>,
     },
+    /// Check a config file for errors without loading it
+    Check {
+        /// Path to the configuration file
+        #[structopt(parse(from_os_str = absolute_path))]
+        path: PathBuf,
+    },
+    /// Print a value of the current configuration
+    Get {
+        /// `gap`, `border_width`, `follow_cursor`, `focused_border_color`, `normal_border_color` or `mod_key`
+        key: ConfigKey,
+    },
+    /// Change a value of the current configuration, it's not written to the file
+    Set {
+        /// `gap`, `border_width`, `follow_cursor`, `focused_border_color`, `normal_border_color` or `mod_key`
+        key: ConfigKey,
+        /// The new value, like `8`, `true`, `#FF0000` or `mod4`
+        value: String,
+    },
+    /// Write the current configuration to the file it was loaded from, replacing it.
+    /// Comments are lost, and files that include others are left alone
+    Save,
+}
+
+/// The values of the config that can be changed on their own
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigKey {
+    Gap,
+    BorderWidth,
+    FollowCursor,
+    FocusedBorderColor,
+    NormalBorderColor,
+    ModKey,
+}
+
+impl FromStr for ConfigKey {
+    type Err = ParseConfigKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "gap" => Ok(Self::Gap),
+            "border_width" => Ok(Self::BorderWidth),
+            "follow_cursor" => Ok(Self::FollowCursor),
+            "focused_border_color" => Ok(Self::FocusedBorderColor),
+            "normal_border_color" => Ok(Self::NormalBorderColor),
+            "mod_key" => Ok(Self::ModKey),
+            _ => Err(ParseConfigKeyError { key: s.to_string() }),
+        }
+    }
+}
+
+impl fmt::Display for ConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key = match self {
+            Self::Gap => "gap",
+            Self::BorderWidth => "border_width",
+            Self::FollowCursor => "follow_cursor",
+            Self::FocusedBorderColor => "focused_border_color",
+            Self::NormalBorderColor => "normal_border_color",
+            Self::ModKey => "mod_key",
+        };
+        write!(f, "{}", key)
+    }
+}
+
+/// Paths are relative to the client, not the window manager
+fn absolute_path(path: &OsStr) -> PathBuf {
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| PathBuf::from(path))
 }
@@ -2,6 +2,7 @@
 mod config_cmd;
 mod layout_cmd;
 mod monitor_cmd;
+mod query_cmd;
 mod tag_cmd;
 mod window_cmd;
 
@@ -10,12 +11,15 @@ use {
     structopt::StructOpt,
 };
 
+use crate::EventKind;
+
 pub use {
-    config_cmd::ConfigSubcommand,
+    config_cmd::{ConfigKey, ConfigSubcommand},
     layout_cmd::LayoutSubcommand,
     monitor_cmd::MonitorSubcommand,
+    query_cmd::QuerySubcommand,
     tag_cmd::TagSubcommand,
-    window_cmd::{WindowSubcommand, WindowToggle},
+    window_cmd::{WindowCommand, WindowSelector, WindowSubcommand, WindowToggle},
 };
 
 #[derive(Deserialize, Serialize, StructOpt, Debug)]
@@ -27,12 +31,32 @@ pub enum Command {
     /// Commands related to tags
     Tag(TagSubcommand),
     #[structopt(alias = "win")]
-    /// Commands related to the currently focused window
-    Window(WindowSubcommand),
+    /// Commands related to the currently focused window, or the one picked by the options
+    Window(WindowCommand),
     /// Commands related to the currently focused monitor
     Monitor(MonitorSubcommand),
     /// Commands related to layouts
     Layout(LayoutSubcommand),
-    /// Print or load a config
+    /// Print, load or change the config
     Config(ConfigSubcommand),
+    /// Get information about the state of the window manager as JSON
+    Query(QuerySubcommand),
+    /// Run a shell command, detached from the window manager
+    Spawn {
+        /// The command, quoted if it has spaces
+        cmd: String,
+    },
+    /// Keep listening for events, printing every one of them as a line of JSON
+    Subscribe {
+        /// The events to listen for (`focus`, `tag`, `layout`, `window`, `monitor`, `config`), all of them if empty
+        events: Vec<EventKind>,
+    },
+}
+
+/// What a client sends to the wm
+#[derive(Deserialize, Serialize, Debug)]
+pub enum Request {
+    Command(Command),
+    /// Commands that run in order, with the windows being updated only once at the end
+    Batch(Vec<Command>),
 }
@@ -3,10 +3,13 @@ use {
     structopt::StructOpt,
 };
 
-use crate::Direction;
+use crate::MonitorTarget;
 
 #[derive(Deserialize, Serialize, StructOpt, Debug)]
 pub enum MonitorSubcommand {
-    /// Focus the monitor in the given direction
-    Focus(Direction),
+    /// Focus the given monitor
+    Focus {
+        /// A direction (`next`/`prev`/`left`/`right`/`above`/`below`), the index of the monitor or its name (ex `DP-1`)
+        target: MonitorTarget,
+    },
 }
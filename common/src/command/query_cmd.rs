@@ -0,0 +1,18 @@
+use {
+    serde::{Deserialize, Serialize},
+    structopt::StructOpt,
+};
+
+#[derive(Deserialize, Serialize, StructOpt, Debug)]
+pub enum QuerySubcommand {
+    /// List all the managed windows
+    Windows,
+    /// List all the monitors
+    Monitors,
+    /// List the tags of every monitor
+    Tags,
+    /// List the available layouts and the one used on every monitor
+    Layouts,
+    /// Show the focused monitor and window
+    Focused,
+}
@@ -3,7 +3,58 @@ use {
     structopt::StructOpt,
 };
 
-use crate::{Destination, Direction};
+use crate::{Destination, Direction, FocusDirection, ParseWindowIdError, SpatialDirection};
+
+/// A window command and the window it acts on
+#[derive(Deserialize, Serialize, StructOpt, Debug)]
+pub struct WindowCommand {
+    #[structopt(flatten)]
+    pub selector: WindowSelector,
+    #[structopt(subcommand)]
+    pub action: WindowSubcommand,
+}
+
+/// Picks the window a command acts on, the focused one if nothing is given.
+/// The options go before the command, ex `rwmc window --class firefox destroy`.
+/// When more than one option is given the window has to match all of them.
+/// `focus next`/`prev` goes through the matching windows, ex `rwmc window --class kitty focus next`
+#[derive(Deserialize, Serialize, StructOpt, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowSelector {
+    /// The window with the given id, ex `0x1a00003`
+    #[structopt(long, parse(try_from_str = parse_window_id))]
+    pub id: Option<u32>,
+    /// A window with the given class or instance
+    #[structopt(long)]
+    pub class: Option<String>,
+    /// A window with a title that matches the given regex
+    #[structopt(long)]
+    pub title: Option<String>,
+    /// The master window
+    #[structopt(long)]
+    pub master: bool,
+    /// The window that had focus before the current one
+    #[structopt(long)]
+    pub last: bool,
+    /// A window that asks for attention
+    #[structopt(long)]
+    pub urgent: bool,
+}
+
+impl WindowSelector {
+    /// If no option is given, so it points to the focused window
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Parse a window id either in hex (`0x1a00003`) or in decimal
+fn parse_window_id(id: &str) -> Result<u32, ParseWindowIdError> {
+    let parsed = match id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => id.parse(),
+    };
+    parsed.map_err(|_| ParseWindowIdError { id: id.to_string() })
+}
 
 #[derive(Deserialize, Serialize, StructOpt, Debug)]
 pub enum WindowSubcommand {
@@ -13,10 +64,20 @@ pub enum WindowSubcommand {
     /// Send the current window to another monitor or tag
     Send(Destination),
     /// Shift focus from the current window
-    Focus(Direction),
+    Focus {
+        /// `next`/`prev` to follow the order of the windows, or `left`/`right`/`up`/`down`
+        direction: FocusDirection,
+    },
     #[structopt(alias = "move")]
     /// Shift the current window up or down
     Shift(Direction),
+    /// Swap the current window with the closest window in the given direction
+    Swap {
+        /// `left`/`right`/`up`/`down`
+        direction: SpatialDirection,
+    },
+    /// Swap the current window with the master window, or with the next one if it's already the master
+    Zoom,
     /// Toggle an option about the current window
     Toggle(WindowToggle),
 }
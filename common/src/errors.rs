@@ -32,6 +32,58 @@ impl fmt::Display for ParseModMaskError {
 
 impl Error for ParseModMaskError {}
 
+#[derive(Debug)]
+pub struct ParseDirectionError {
+    pub direction: String,
+}
+
+impl fmt::Display for ParseDirectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid direction: {}", self.direction)
+    }
+}
+
+impl Error for ParseDirectionError {}
+
+#[derive(Debug)]
+pub struct ParseEventKindError {
+    pub kind: String,
+}
+
+impl fmt::Display for ParseEventKindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid event: {}", self.kind)
+    }
+}
+
+impl Error for ParseEventKindError {}
+
+#[derive(Debug)]
+pub struct ParseConfigKeyError {
+    pub key: String,
+}
+
+impl fmt::Display for ParseConfigKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid config key: {}", self.key)
+    }
+}
+
+impl Error for ParseConfigKeyError {}
+
+#[derive(Debug)]
+pub struct ParseWindowIdError {
+    pub id: String,
+}
+
+impl fmt::Display for ParseWindowIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid window id: {}", self.id)
+    }
+}
+
+impl Error for ParseWindowIdError {}
+
 #[derive(Debug)]
 pub struct ParseColorError {
     pub color: String,
@@ -85,3 +137,32 @@ impl From<io::Error> for LoadConfigError {
 }
 
 impl Error for LoadConfigError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The other side didn't start with a valid handshake
+    BadHandshake,
+    /// The two sides speak different versions of the protocol
+    VersionMismatch { ours: u8, theirs: u8 },
+    /// The message is bigger than `MAX_MESSAGE_SIZE` for requests, or `MAX_REPLY_SIZE` for replies
+    TooBig(usize),
+    /// The message is not valid UTF-8
+    InvalidUtf8,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadHandshake => write!(f, "Invalid handshake"),
+            Self::VersionMismatch { ours, theirs } => write!(
+                f,
+                "Protocol version mismatch, expected {} but got {}",
+                ours, theirs
+            ),
+            Self::TooBig(len) => write!(f, "Message is too big: {} bytes", len),
+            Self::InvalidUtf8 => write!(f, "Message is not valid UTF-8"),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
@@ -0,0 +1,79 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ParseEventKindError, TagId};
+
+/// Something that happened in the wm, sent to subscribed clients as a line of JSON
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WmEvent {
+    /// The focused window or monitor changed
+    Focus {
+        monitor: String,
+        window: Option<u32>,
+    },
+    /// The visible tags of a monitor changed
+    Tag {
+        monitor: String,
+        visible: Vec<TagId>,
+    },
+    /// The layout of a monitor changed
+    Layout { monitor: String, layout: String },
+    /// A new window is now managed by the wm
+    WindowManaged { window: u32 },
+    /// A window is not managed by the wm anymore
+    WindowUnmanaged { window: u32 },
+    /// Monitors got added, removed or focused
+    Monitor {
+        focused: String,
+        monitors: Vec<String>,
+    },
+    /// The config file was loaded. If it failed `error` is set and the old config is still used
+    Config {
+        path: Option<String>,
+        error: Option<String>,
+    },
+}
+
+impl WmEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Focus { .. } => EventKind::Focus,
+            Self::Tag { .. } => EventKind::Tag,
+            Self::Layout { .. } => EventKind::Layout,
+            Self::WindowManaged { .. } | Self::WindowUnmanaged { .. } => EventKind::Window,
+            Self::Monitor { .. } => EventKind::Monitor,
+            Self::Config { .. } => EventKind::Config,
+        }
+    }
+}
+
+/// The kinds of events a client can subscribe to
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    Focus,
+    Tag,
+    Layout,
+    Window,
+    Monitor,
+    Config,
+}
+
+impl FromStr for EventKind {
+    type Err = ParseEventKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "focus" => Ok(Self::Focus),
+            "tag" | "tags" => Ok(Self::Tag),
+            "layout" | "layouts" => Ok(Self::Layout),
+            "window" | "windows" => Ok(Self::Window),
+            "monitor" | "monitors" => Ok(Self::Monitor),
+            "config" => Ok(Self::Config),
+            _ => Err(ParseEventKindError {
+                kind: s.to_string(),
+            }),
+        }
+    }
+}
@@ -1,44 +1,31 @@
 mod command;
 mod errors;
+mod event;
+mod monitor_target;
+mod protocol;
+mod response;
 mod tag_id;
+#[cfg(test)]
+mod tests;
 
-use std::{io::Read, os::unix::net::UnixStream};
+use std::str::FromStr;
 
 use {
-    anyhow::{Context, Result},
     serde::{Deserialize, Serialize},
     structopt::StructOpt,
 };
 
-pub use {command::*, errors::*, tag_id::TagId};
-
-/// A function that serialises a message and produces a string that can be directly sent to the wm or the client
-pub fn into_message<T: Serialize>(msg: T) -> Result<String> {
-    let json = serde_json::to_string(&msg).context("Failed to serialise")?;
-    Ok(format!("{:0>4}{}", json.len(), json))
-}
-
-/// Read a message from the socket
-pub fn read_message(stream: &mut UnixStream) -> Result<String> {
-    // First for bytes we read should be the length of the command that follows
-    let mut cmd_len = [0; 4];
-    stream
-        .read_exact(&mut cmd_len)
-        .context("There was an error in the wm. Failed to read response.")?;
-    // If it can't be parsed to a number we simply don't care about it
-    let cmd_len = String::from_utf8(cmd_len.to_vec())
-        .unwrap()
-        .parse::<usize>()
-        .unwrap();
-    let mut handle = stream.take(cmd_len as u64);
-    let mut reply = String::with_capacity(cmd_len);
-    handle
-        .read_to_string(&mut reply)
-        .context("Failed to read response.")?;
-    Ok(reply)
-}
+pub use {
+    command::*,
+    errors::*,
+    event::{EventKind, WmEvent},
+    monitor_target::MonitorTarget,
+    protocol::*,
+    response::Response,
+    tag_id::TagId,
+};
 
-#[derive(Deserialize, Serialize, StructOpt, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, StructOpt, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Direction {
     #[structopt(aliases = &["prev", "previous"])]
     Up,
@@ -46,8 +33,57 @@ pub enum Direction {
     Down,
 }
 
-#[derive(Deserialize, Serialize, StructOpt, Debug, Copy, Clone)]
+/// A direction based on where things are on the screen
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpatialDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl FromStr for SpatialDirection {
+    type Err = ParseDirectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "up" | "above" => Ok(Self::Up),
+            "down" | "below" => Ok(Self::Down),
+            _ => Err(ParseDirectionError {
+                direction: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Where to move the focus to, either by following the order of the windows or by their position
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FocusDirection {
+    List(Direction),
+    Spatial(SpatialDirection),
+}
+
+impl FromStr for FocusDirection {
+    type Err = ParseDirectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "next" => Ok(Self::List(Direction::Down)),
+            "prev" | "previous" => Ok(Self::List(Direction::Up)),
+            _ => s.parse().map(Self::Spatial),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, StructOpt, Debug, Clone)]
 pub enum Destination {
-    Tag { tag_id: TagId },
-    Monitor(Direction),
+    Tag {
+        tag_id: TagId,
+    },
+    Monitor {
+        /// A direction (`next`/`prev`/`left`/`right`/`above`/`below`), the index of the monitor or its name (ex `DP-1`)
+        target: MonitorTarget,
+    },
 }
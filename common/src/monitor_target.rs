@@ -0,0 +1,54 @@
+use std::{
+    convert::Infallible,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Direction, SpatialDirection};
+
+/// A way to point to a monitor, either relative to the focused one, by its index or by its name
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum MonitorTarget {
+    /// The next or previous monitor in the list
+    Direction(Direction),
+    /// The monitor next to the focused one on the screen
+    Spatial(SpatialDirection),
+    /// The index of the monitor, starting from 0
+    Index(usize),
+    /// The name of the monitor as given by RandR (ex `DP-1`)
+    Name(String),
+}
+
+impl FromStr for MonitorTarget {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(index) = s.parse::<usize>() {
+            return Ok(Self::Index(index));
+        }
+        let target = match s.to_ascii_lowercase().as_str() {
+            "up" | "prev" | "previous" => Self::Direction(Direction::Up),
+            "down" | "next" => Self::Direction(Direction::Down),
+            // `up` and `down` are taken above, so this only matches `left`, `right`, `above` and `below`
+            _ => match s.parse::<SpatialDirection>() {
+                Ok(dir) => Self::Spatial(dir),
+                Err(_) => Self::Name(s.to_string()),
+            },
+        };
+        Ok(target)
+    }
+}
+
+impl Display for MonitorTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Direction(dir) => write!(f, "{:?}", dir),
+            Self::Spatial(dir) => write!(f, "{:?}", dir),
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! How the wm and the clients talk to each other.
+//!
+//! A client connects and sends a handshake (`RWM` followed by the protocol version),
+//! the wm answers with its own handshake and if the versions match messages follow.
+//! Every message is JSON prefixed by its length as a big endian `u32`.
+//! A client can send any number of requests on one connection, each getting a reply in order,
+//! until it subscribes or closes its end.
+
+use std::{
+    env,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+use {
+    anyhow::{Context, Result},
+    serde::Serialize,
+};
+
+use crate::ProtocolError;
+
+/// The version of the protocol, bumped on every incompatible change.
+/// 4: connections stay open for more requests after a reply
+pub const PROTOCOL_VERSION: u8 = 4;
+/// The length of the handshake
+pub const HANDSHAKE_LEN: usize = 4;
+/// The biggest request, without the length, that the wm accepts
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// The biggest reply, without the length. Queries can return a lot more than any request
+pub const MAX_REPLY_SIZE: usize = 16 * 1024 * 1024;
+
+const MAGIC: &[u8; 3] = b"RWM";
+const LEN_PREFIX: usize = 4;
+
+/// The path of the socket used to talk to the wm.
+/// If no path is given it is `$XDG_RUNTIME_DIR/rwm-<display>.sock` (or in `/tmp` if that's not set)
+/// so every X display gets its own socket.
+pub fn socket_path(path: Option<PathBuf>) -> PathBuf {
+    if let Some(path) = path {
+        return path;
+    }
+
+    let display = env::var("DISPLAY").unwrap_or_default();
+    let mut path = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    path.push(format!("rwm-{}.sock", display_name(&display)));
+    path
+}
+
+/// Turn the value of $DISPLAY to something that can be used in a file name.
+/// The screen number is dropped since it's the same server, so `:1` and `:1.0` are both `1`
+pub(crate) fn display_name(display: &str) -> String {
+    let (host, display) = display.rsplit_once(':').unwrap_or(("", display));
+    let number = display.split('.').next().unwrap_or_default();
+    let number = if number.is_empty() { "0" } else { number };
+
+    if host.is_empty() {
+        number.to_string()
+    } else {
+        format!("{}-{}", host.replace('/', "_"), number)
+    }
+}
+
+/// The handshake that starts every connection
+pub fn handshake() -> [u8; HANDSHAKE_LEN] {
+    [MAGIC[0], MAGIC[1], MAGIC[2], PROTOCOL_VERSION]
+}
+
+/// Check the handshake at the start of `buf`, returning `None` if it's not all there yet
+pub fn parse_handshake(buf: &[u8]) -> Result<Option<()>, ProtocolError> {
+    if buf.len() < HANDSHAKE_LEN {
+        return Ok(None);
+    }
+    if &buf[..MAGIC.len()] != MAGIC {
+        return Err(ProtocolError::BadHandshake);
+    }
+    match buf[MAGIC.len()] {
+        PROTOCOL_VERSION => Ok(Some(())),
+        theirs => Err(ProtocolError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs,
+        }),
+    }
+}
+
+/// Connect to the wm and do the handshake
+pub fn connect(path: &Path) -> Result<UnixStream> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Error connecting to the socket `{}`", path.display()))?;
+    stream
+        .write_all(&handshake())
+        .context("Error sending the handshake")?;
+
+    let mut reply = [0; HANDSHAKE_LEN];
+    stream
+        .read_exact(&mut reply)
+        .context("The wm didn't reply to the handshake")?;
+    parse_handshake(&reply)?;
+
+    Ok(stream)
+}
+
+/// Serialise a message to bytes that can be directly sent to the wm or the client.
+/// Requests over `MAX_MESSAGE_SIZE` still get refused by the wm
+pub fn into_message<T: Serialize>(msg: T) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(&msg).context("Failed to serialise")?;
+    if json.len() > MAX_REPLY_SIZE {
+        return Err(ProtocolError::TooBig(json.len()).into());
+    }
+
+    let mut message = Vec::with_capacity(LEN_PREFIX + json.len());
+    message.extend_from_slice(&(json.len() as u32).to_be_bytes());
+    message.extend_from_slice(&json);
+    Ok(message)
+}
+
+/// Try to get a whole request from the start of `buf`, returning it along with the number of bytes it took.
+/// Returns `None` if there isn't a whole message in the buffer yet
+pub fn parse_message(buf: &[u8]) -> Result<Option<(String, usize)>, ProtocolError> {
+    if buf.len() < LEN_PREFIX {
+        return Ok(None);
+    }
+    let msg_len = message_len(&buf[..LEN_PREFIX], MAX_MESSAGE_SIZE)?;
+    let end = LEN_PREFIX + msg_len;
+    if buf.len() < end {
+        return Ok(None);
+    }
+
+    let msg =
+        String::from_utf8(buf[LEN_PREFIX..end].to_vec()).map_err(|_| ProtocolError::InvalidUtf8)?;
+    Ok(Some((msg, end)))
+}
+
+/// Read a whole reply from the stream
+pub fn read_message<R: Read>(stream: &mut R) -> Result<String> {
+    let mut prefix = [0; LEN_PREFIX];
+    stream
+        .read_exact(&mut prefix)
+        .context("There was an error in the wm. Failed to read response.")?;
+    let msg_len = message_len(&prefix, MAX_REPLY_SIZE)?;
+
+    let mut msg = vec![0; msg_len];
+    stream
+        .read_exact(&mut msg)
+        .context("Failed to read response.")?;
+    Ok(String::from_utf8(msg).map_err(|_| ProtocolError::InvalidUtf8)?)
+}
+
+fn message_len(prefix: &[u8], max: usize) -> Result<usize, ProtocolError> {
+    let mut len = [0; LEN_PREFIX];
+    len.copy_from_slice(prefix);
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max {
+        Err(ProtocolError::TooBig(len))
+    } else {
+        Ok(len)
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// The reply the wm sends to the client for every command
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", content = "data", rename_all = "lowercase")]
+pub enum Response {
+    /// The command was executed and there is nothing to report back
+    Success,
+    /// The command failed with the given message
+    Error(String),
+    /// The command was executed and produced the given data
+    Data(serde_json::Value),
+    /// The responses to a batch of commands, in the same order as the commands.
+    /// An extra error follows them if the windows couldn't be updated afterwards
+    Batch(Vec<Response>),
+}
+
+impl Response {
+    /// Turn the result of a command to a response, using the whole chain of causes for errors
+    pub fn from_result(result: anyhow::Result<Self>) -> Self {
+        result.unwrap_or_else(|e| Self::Error(format!("{:#}", e)))
+    }
+}
@@ -7,7 +7,9 @@ use {
 
 use crate::TagValueError;
 
-#[derive(Display, Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[derive(
+    Display, Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash,
+)]
 pub struct TagId(u8);
 
 impl TagId {
@@ -15,6 +17,11 @@ impl TagId {
     pub fn from_int_unchecked<N: Into<u8>>(n: N) -> Self {
         Self(n.into())
     }
+
+    /// Whether the id is one of the tags, from 1 to 9
+    pub fn is_valid(&self) -> bool {
+        (1..=9).contains(&self.0)
+    }
 }
 
 impl FromStr for TagId {
@@ -0,0 +1,168 @@
+use std::io::Cursor;
+
+use structopt::StructOpt;
+
+use crate::{
+    handshake, into_message, parse_handshake, parse_message, protocol::display_name, read_message,
+    Command, ProtocolError, Request, Response, WindowSelector, MAX_MESSAGE_SIZE, MAX_REPLY_SIZE,
+    PROTOCOL_VERSION,
+};
+
+#[test]
+fn test_message_round_trip() {
+    let msg = into_message(Command::Quit).unwrap();
+    let (parsed, len) = parse_message(&msg).unwrap().unwrap();
+    assert_eq!(len, msg.len());
+    assert!(matches!(
+        serde_json::from_str::<Command>(&parsed).unwrap(),
+        Command::Quit
+    ));
+
+    // Messages over 9999 bytes used to break the protocol, and replies can be bigger than requests
+    let big = Response::Error("a".repeat(MAX_MESSAGE_SIZE * 4));
+    let msg = into_message(&big).unwrap();
+    let read = read_message(&mut Cursor::new(&msg)).unwrap();
+    assert_eq!(serde_json::from_str::<Response>(&read).unwrap(), big);
+}
+
+#[test]
+fn test_batch_round_trip() {
+    let request = Request::Batch(vec![Command::Quit, Command::Quit]);
+    let msg = into_message(&request).unwrap();
+    let (parsed, _) = parse_message(&msg).unwrap().unwrap();
+    match serde_json::from_str::<Request>(&parsed).unwrap() {
+        Request::Batch(cmds) => assert_eq!(cmds.len(), 2),
+        other => panic!("Expected a batch, got {:?}", other),
+    }
+
+    let reply = Response::Batch(vec![Response::Success, Response::Error(String::from("no"))]);
+    let json = serde_json::to_string(&reply).unwrap();
+    match serde_json::from_str::<Response>(&json).unwrap() {
+        Response::Batch(replies) => {
+            assert!(matches!(replies[0], Response::Success));
+            assert!(matches!(&replies[1], Response::Error(e) if e == "no"));
+        }
+        other => panic!("Expected a batch, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_response_shape() {
+    let cases = [
+        (Response::Success, r#"{"status":"success"}"#),
+        (
+            Response::Error(String::from("no")),
+            r#"{"status":"error","data":"no"}"#,
+        ),
+        (
+            Response::Data(serde_json::json!({ "windows": [1, 2] })),
+            r#"{"status":"data","data":{"windows":[1,2]}}"#,
+        ),
+        (
+            Response::Batch(vec![
+                Response::Success,
+                Response::Data(serde_json::json!(3)),
+            ]),
+            r#"{"status":"batch","data":[{"status":"success"},{"status":"data","data":3}]}"#,
+        ),
+    ];
+    for (response, json) in cases {
+        // Clients depend on this JSON, so it has to stay the same
+        assert_eq!(serde_json::to_string(&response).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Response>(json).unwrap(), response);
+    }
+}
+
+#[test]
+fn test_partial_messages() {
+    let msg = into_message(Response::Success).unwrap();
+    for end in 0..msg.len() {
+        assert_eq!(parse_message(&msg[..end]).unwrap(), None);
+    }
+
+    // Whatever follows a message is not part of it
+    let mut two = msg.clone();
+    two.extend_from_slice(&msg);
+    let (_, len) = parse_message(&two).unwrap().unwrap();
+    assert_eq!(len, msg.len());
+}
+
+#[test]
+fn test_malformed_messages() {
+    let too_big = ((MAX_MESSAGE_SIZE + 1) as u32).to_be_bytes();
+    assert_eq!(
+        parse_message(&too_big),
+        Err(ProtocolError::TooBig(MAX_MESSAGE_SIZE + 1))
+    );
+    // Replies can be much bigger than requests
+    assert!(read_message(&mut Cursor::new(&too_big)).is_err_and(|e| !e.to_string().contains("big")));
+    let too_big = ((MAX_REPLY_SIZE + 1) as u32).to_be_bytes();
+    assert!(read_message(&mut Cursor::new(&too_big)).is_err_and(|e| e.to_string().contains("big")));
+
+    let invalid_utf8 = [0, 0, 0, 2, 0xC3, 0x28];
+    assert_eq!(
+        parse_message(&invalid_utf8),
+        Err(ProtocolError::InvalidUtf8)
+    );
+    assert!(read_message(&mut Cursor::new(&invalid_utf8)).is_err());
+
+    // The old ASCII length prefix
+    assert!(read_message(&mut Cursor::new(b"0004\"ab\"")).is_err());
+    // Cut short
+    assert!(read_message(&mut Cursor::new([0, 0, 0, 10, b'{'])).is_err());
+}
+
+#[test]
+fn test_handshake() {
+    let hello = handshake();
+    assert_eq!(parse_handshake(&hello), Ok(Some(())));
+    assert_eq!(parse_handshake(&hello[..2]), Ok(None));
+    assert_eq!(parse_handshake(b"0004"), Err(ProtocolError::BadHandshake));
+    assert_eq!(
+        parse_handshake(&[b'R', b'W', b'M', PROTOCOL_VERSION + 1]),
+        Err(ProtocolError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: PROTOCOL_VERSION + 1,
+        })
+    );
+}
+
+#[test]
+fn test_display_name() {
+    assert_eq!(display_name(":0"), "0");
+    assert_eq!(display_name(":1.0"), "1");
+    assert_eq!(display_name("localhost:10.0"), "localhost-10");
+    assert_eq!(display_name(""), "0");
+}
+
+#[test]
+fn test_window_selector() {
+    let parse = |args: &[&str]| {
+        let args = std::iter::once("rwmc").chain(["window"].iter().chain(args).copied());
+        match Command::from_iter_safe(args).unwrap() {
+            Command::Window(cmd) => cmd.selector,
+            other => panic!("Expected a window command, got {:?}", other),
+        }
+    };
+
+    assert!(parse(&["zoom"]).is_empty());
+    assert_eq!(
+        parse(&["--id", "0x1a00003", "zoom"]),
+        WindowSelector {
+            id: Some(0x1a00003),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        parse(&["--class", "firefox", "--urgent", "send", "tag", "3"]),
+        WindowSelector {
+            class: Some(String::from("firefox")),
+            urgent: true,
+            ..Default::default()
+        }
+    );
+    assert_eq!(parse(&["--id", "42", "destroy"]).id, Some(42));
+    // Given after the command it would be silently ignored, so it must fail instead
+    assert!(Command::from_iter_safe(["rwmc", "window", "destroy", "--id", "42"]).is_err());
+    assert!(Command::from_iter_safe(["rwmc", "window", "--id", "zz", "zoom"]).is_err());
+}
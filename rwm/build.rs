@@ -0,0 +1,25 @@
+//! Generates the man page of rwm from its command line options.
+//! It's put in `$OUT_DIR`, or in `$RWM_MAN_DIR` if that's set
+
+use std::{env, fs, path::PathBuf};
+
+use structopt::StructOpt;
+
+#[allow(dead_code)]
+mod opt {
+    include!("src/opt.rs");
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/opt.rs");
+    println!("cargo:rerun-if-env-changed=RWM_MAN_DIR");
+
+    let dir = env::var_os("RWM_MAN_DIR")
+        .or_else(|| env::var_os("OUT_DIR"))
+        .map(PathBuf::from)
+        .expect("OUT_DIR is always set by cargo");
+    fs::create_dir_all(&dir).expect("Failed to create the man page directory");
+
+    let page = clap_docs::man_page(&opt::Opt::clap(), 1, env!("CARGO_PKG_DESCRIPTION"));
+    fs::write(dir.join("rwm.1"), page).expect("Failed to write the man page");
+}
@@ -0,0 +1,13 @@
+//! The atoms the wm uses that X doesn't predefine, interned once at start up
+
+x11rb::atom_manager! {
+    pub(crate) Atoms: AtomsCookie {
+        UTF8_STRING,
+        WM_WINDOW_ROLE,
+        _NET_SUPPORTED,
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_WINDOW_TYPE,
+    }
+}
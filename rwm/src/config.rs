@@ -1,14 +1,32 @@
-use std::{collections::HashMap, convert::TryFrom, fs::File, path::PathBuf};
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use {
-    anyhow::{bail, Context, Result},
+    anyhow::{anyhow, bail, Context, Result},
+    ron::{extensions::Extensions, Options},
     serde::{Deserialize, Serialize},
 };
 
-use crate::{color::Color, layouts::LayoutType, mod_mask::XModMask, spawn_rule::SpawnRule};
-use common::TagId;
+use crate::{
+    color::Color,
+    layouts::LayoutType,
+    mod_mask::XModMask,
+    spawn_rule::{SpawnRule, WINDOW_TYPES},
+    utils::expand_tilde,
+};
+use common::ConfigKey;
+
+/// Where the config file is, relative to a config dir
+const CONFIG_FILE: &str = "rwm/config.ron";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct Config {
     pub(crate) border_width: u32,
     pub(crate) focused_border_color: Color,
@@ -20,17 +38,22 @@ pub struct Config {
     pub(crate) follow_cursor: bool,
     /// Useless gap between windows
     pub(crate) gap: u32,
-    /// This is used only for printing and reading to and from a config file
-    /// It gets broken to `class_rules` and `name_rules`, these are actually used by the wm
-    rules: Vec<SpawnRule>,
-    #[serde(skip)]
-    pub(crate) class_rules: HashMap<String, Vec<TagId>>,
-    #[serde(skip)]
-    pub(crate) name_rules: HashMap<String, Vec<TagId>>,
+    /// Shell commands to run once the wm has started
+    pub(crate) autostart: Vec<String>,
+    /// Shell commands to run every time the config is loaded again
+    pub(crate) on_reload: Vec<String>,
+    /// Every rule that matches a new window is applied, in order
+    pub(crate) rules: Vec<SpawnRule>,
     /// The path to the currently loaded config file.
     /// None if there is no config loaded
     #[serde(skip)]
     pub(crate) path: Option<PathBuf>,
+    /// Every file the config was read from, the included ones too
+    #[serde(skip)]
+    pub(crate) files: Vec<PathBuf>,
+    /// When the file was last written by `save`
+    #[serde(skip)]
+    pub(crate) saved: Option<SystemTime>,
 }
 
 impl Default for Config {
@@ -52,48 +75,109 @@ impl Default for Config {
             ],
             follow_cursor: true,
             gap: 4,
+            autostart: vec![],
+            on_reload: vec![],
             rules: vec![],
-            class_rules: HashMap::new(),
-            name_rules: HashMap::new(),
             path: None,
+            files: vec![],
+            saved: None,
         }
     }
 }
 
 impl Config {
-    /// Split SpawnRules to two HashMaps, one with class rules and one with name rules
-    fn extract_rules(
-        rules: &[SpawnRule],
-    ) -> (HashMap<String, Vec<TagId>>, HashMap<String, Vec<TagId>>) {
-        let mut class_rules = HashMap::new();
-        let mut name_rules = HashMap::new();
-        for rule in rules.iter() {
-            match rule {
-                SpawnRule::ClassName(name, tag_ids) => {
-                    class_rules.insert(name.to_owned(), tag_ids.to_owned())
-                }
-                SpawnRule::WmName(name, tag_ids) => {
-                    name_rules.insert(name.to_owned(), tag_ids.to_owned())
-                }
-            };
+    /// The places a config file is looked for when none is given, in order.
+    /// That's `rwm/config.ron` in the user's config dir and then in every system config dir
+    pub(crate) fn search_paths() -> Vec<PathBuf> {
+        config_paths(
+            dirs::config_dir().as_deref(),
+            env::var_os("XDG_CONFIG_DIRS").as_deref(),
+        )
+    }
+
+    /// Where the user's own config file goes, None if there is no user config dir
+    pub(crate) fn user_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    /// Find the config file to use when none is given
+    pub(crate) fn find() -> Option<PathBuf> {
+        Self::search_paths().into_iter().find(|path| path.is_file())
+    }
+
+    /// A value of the config, written like in the config file
+    pub(crate) fn get(&self, key: ConfigKey) -> String {
+        match key {
+            ConfigKey::Gap => self.gap.to_string(),
+            ConfigKey::BorderWidth => self.border_width.to_string(),
+            ConfigKey::FollowCursor => self.follow_cursor.to_string(),
+            ConfigKey::FocusedBorderColor => self.focused_border_color.to_string(),
+            ConfigKey::NormalBorderColor => self.normal_border_color.to_string(),
+            ConfigKey::ModKey => self.mod_key.to_string(),
+        }
+    }
+
+    /// Change a value of the config, parsing it like the config file does
+    pub(crate) fn set(&mut self, key: ConfigKey, value: &str) -> Result<()> {
+        let invalid = || format!("Invalid value `{}` for `{}`", value, key);
+        match key {
+            ConfigKey::Gap => self.gap = value.trim().parse().with_context(invalid)?,
+            ConfigKey::BorderWidth => {
+                self.border_width = value.trim().parse().with_context(invalid)?
+            }
+            ConfigKey::FollowCursor => {
+                self.follow_cursor = value.trim().parse().with_context(invalid)?
+            }
+            ConfigKey::FocusedBorderColor => {
+                self.focused_border_color = value.parse().with_context(invalid)?
+            }
+            ConfigKey::NormalBorderColor => {
+                self.normal_border_color = value.parse().with_context(invalid)?
+            }
+            ConfigKey::ModKey => self.mod_key = value.parse().with_context(invalid)?,
+        }
+        Ok(())
+    }
+
+    /// Write the config to the file it was loaded from.
+    /// Files that include others aren't replaced, the included files would be merged into them
+    pub(crate) fn save(&mut self) -> Result<()> {
+        let path = self
+            .path
+            .as_ref()
+            .context("No config file is loaded, there is nowhere to save the config")?;
+        if self.files.len() > 1 {
+            bail!(
+                "`{}` includes other files, saving would merge them into it. Edit it instead",
+                path.display()
+            );
         }
+        let config = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .context("Failed to serialise current configuration")?;
+        fs::write(path, config).with_context(|| format!("Failed to write `{}`", path.display()))?;
+        log::info!("Saved the config to {}", path.display());
+        self.saved = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Ok(())
+    }
 
-        (class_rules, name_rules)
+    /// If the file is still the one `save` wrote, so loading it again would change nothing
+    pub(crate) fn is_saved(&self) -> bool {
+        let modified = self
+            .path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+        self.saved.is_some() && self.saved == modified
     }
 
-    pub(crate) fn from_file(path: PathBuf) -> Result<Self> {
-        let conf_file =
-            File::open(&path).context(format!("Failed to open `{}`", path.display()))?;
-        let mut config: Self = ron::de::from_reader(conf_file)
-            .context(format!("Failed to parse `{}`", path.display()))?;
+    /// Read and validate the config file and the files it includes, without applying it
+    pub(crate) fn parse(path: &Path) -> Result<Self> {
+        Ok(ConfigFile::load(path, &mut Vec::new())?.into_config())
+    }
 
+    pub(crate) fn from_file(path: PathBuf) -> Result<Self> {
+        let mut config = Self::parse(&path)?;
         log::info!("Loaded config from file {}", path.display());
         config.path = Some(path);
-
-        let (class_rules, name_rules) = Self::extract_rules(&config.rules);
-        config.class_rules = class_rules;
-        config.name_rules = name_rules;
-
         Ok(config)
     }
 
@@ -105,19 +189,257 @@ impl Config {
 
         log::trace!("Replacing config with {}", path.display());
 
-        let conf_file =
-            File::open(&path).context(format!("Failed to open `{}`", path.display()))?;
-        let mut new_config: Self = ron::de::from_reader(conf_file)
-            .context(format!("Failed to parse `{}`", path.display()))?;
+        let mut new_config = Self::parse(&path)?;
         new_config.path = Some(path);
 
         let _ = std::mem::replace(self, new_config);
 
-        let (class_rules, name_rules) = Self::extract_rules(&self.rules);
-        self.class_rules = class_rules;
-        self.name_rules = name_rules;
-
         log::info!("Loaded config from file");
         Ok(())
     }
 }
+
+/// A config file as it's written. Every field is optional so files can be partial,
+/// the ones that are missing come from the files it includes or the default config
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// Files this one builds on, later ones override earlier ones.
+    /// Relative paths are relative to this file
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    border_width: Option<u32>,
+    focused_border_color: Option<Color>,
+    normal_border_color: Option<Color>,
+    mod_key: Option<XModMask>,
+    layouts: Option<Vec<LayoutType>>,
+    follow_cursor: Option<bool>,
+    gap: Option<u32>,
+    /// This and the rest of the lists are added to the lists of the included files
+    #[serde(default)]
+    autostart: Vec<String>,
+    #[serde(default)]
+    on_reload: Vec<String>,
+    #[serde(default)]
+    rules: Vec<SpawnRule>,
+    /// The files read to build this one
+    #[serde(skip)]
+    files: Vec<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Read the file along with the files it includes, layered under it.
+    /// `including` holds the files on the way here, to catch include cycles
+    fn load(path: &Path, including: &mut Vec<PathBuf>) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to open `{}`", path.display()))?;
+        let mut file = parse_ron(&content)
+            .map_err(|(line, col, e)| anyhow!("{}:{}:{}: {}", path.display(), line, col, e))?;
+
+        report(path, file.validate())?;
+        report(path, file.duplicate_rules())?;
+
+        let canonical = fs::canonicalize(path)
+            .with_context(|| format!("Failed to open `{}`", path.display()))?;
+        if including.contains(&canonical) {
+            bail!("`{}` ends up including itself", path.display());
+        }
+        including.push(canonical.clone());
+        file.files.push(canonical);
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut layered = Self::default();
+        for include in &file.include {
+            // Joining an absolute path replaces the directory
+            let include = dir.join(expand_tilde(include));
+            layered.merge(Self::load(&include, including)?);
+        }
+        including.pop();
+
+        layered.merge(file);
+        Ok(layered)
+    }
+
+    /// Check what can't be checked while parsing, returning every problem found.
+    /// Duplicate rules are checked by `duplicate_rules` once the rest is fine
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if matches!(&self.layouts, Some(layouts) if layouts.is_empty()) {
+            problems.push(String::from(
+                "There needs to be at least one layout in the config",
+            ));
+        }
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            let (rule_name, tag_ids) = match rule {
+                SpawnRule::ClassName(name, tag_ids) => {
+                    (format!("rule for the class `{}`", name), Some(tag_ids))
+                }
+                SpawnRule::WmName(name, tag_ids) => (
+                    format!("rule for the window name `{}`", name),
+                    Some(tag_ids),
+                ),
+                SpawnRule::Rule {
+                    window_type,
+                    tags,
+                    geometry,
+                    ..
+                } => {
+                    let rule_name = format!("rule number {}", index + 1);
+                    if rule.is_empty() {
+                        problems.push(format!("The {} has nothing to match windows on", rule_name));
+                    }
+                    if let Some(kind) = window_type {
+                        if !WINDOW_TYPES.contains(&kind.as_str()) {
+                            problems.push(format!(
+                                "The {} uses the window type `{}`, which is not one of {}",
+                                rule_name,
+                                kind,
+                                WINDOW_TYPES.join(", ")
+                            ));
+                        }
+                    }
+                    if let Some(geometry) = geometry {
+                        if geometry.width == 0 || geometry.height == 0 {
+                            problems.push(format!(
+                                "The {} gives windows a size of {}x{}, they can't be empty",
+                                rule_name, geometry.width, geometry.height
+                            ));
+                        }
+                    }
+                    (rule_name, tags.as_ref())
+                }
+            };
+            for tag_id in tag_ids.into_iter().flatten() {
+                if !tag_id.is_valid() {
+                    problems.push(format!(
+                        "The {} uses tag {}, tags go from 1 to 9",
+                        rule_name, tag_id
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// The `ClassName` and `WmName` rules that are there more than once in this file.
+    /// Included files can have the same ones, they're applied in order
+    fn duplicate_rules(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut problems = Vec::new();
+        for rule in self.rules.iter() {
+            let (kind, name) = match rule {
+                SpawnRule::ClassName(name, _) => ("class", name),
+                SpawnRule::WmName(name, _) => ("window name", name),
+                SpawnRule::Rule { .. } => continue,
+            };
+            if !seen.insert((kind, name)) {
+                problems.push(format!(
+                    "There is more than one rule for the {} `{}`",
+                    kind, name
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Override the fields set in `other` and add its lists
+    fn merge(&mut self, other: Self) {
+        self.border_width = other.border_width.or(self.border_width);
+        self.focused_border_color = other.focused_border_color.or(self.focused_border_color);
+        self.normal_border_color = other.normal_border_color.or(self.normal_border_color);
+        self.mod_key = other.mod_key.or_else(|| self.mod_key.take());
+        self.layouts = other.layouts.or_else(|| self.layouts.take());
+        self.follow_cursor = other.follow_cursor.or(self.follow_cursor);
+        self.gap = other.gap.or(self.gap);
+        self.autostart.extend(other.autostart);
+        self.on_reload.extend(other.on_reload);
+        self.rules.extend(other.rules);
+        self.files.extend(other.files);
+    }
+
+    /// Fill in what's missing with the default config
+    fn into_config(self) -> Config {
+        let default = Config::default();
+        Config {
+            border_width: self.border_width.unwrap_or(default.border_width),
+            focused_border_color: self
+                .focused_border_color
+                .unwrap_or(default.focused_border_color),
+            normal_border_color: self
+                .normal_border_color
+                .unwrap_or(default.normal_border_color),
+            mod_key: self.mod_key.unwrap_or(default.mod_key),
+            layouts: self.layouts.unwrap_or(default.layouts),
+            follow_cursor: self.follow_cursor.unwrap_or(default.follow_cursor),
+            gap: self.gap.unwrap_or(default.gap),
+            autostart: self.autostart,
+            on_reload: self.on_reload,
+            rules: self.rules,
+            files: self.files,
+            ..default
+        }
+    }
+}
+
+/// Fail with every problem found in the file, one per line
+fn report(path: &Path, problems: Vec<String>) -> Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let problems = problems
+        .iter()
+        .map(|problem| format!("{}: {}", path.display(), problem))
+        .collect::<Vec<_>>();
+    bail!(problems.join("\n"))
+}
+
+/// The config file in the user config dir and then in each of the system config dirs.
+/// The system config dirs are separated by `:` and default to `/etc/xdg`, as the XDG spec says
+pub(crate) fn config_paths(
+    config_home: Option<&Path>,
+    config_dirs: Option<&OsStr>,
+) -> Vec<PathBuf> {
+    let config_dirs = config_dirs
+        .and_then(OsStr::to_str)
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or("/etc/xdg");
+
+    config_home
+        .into_iter()
+        .map(Path::to_path_buf)
+        .chain(
+            config_dirs
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        )
+        .map(|dir| dir.join(CONFIG_FILE))
+        .collect()
+}
+
+/// Parse the config, returning the line and column of the error on failure
+fn parse_ron(content: &str) -> Result<ConfigFile, (usize, usize, ron::error::ErrorCode)> {
+    let located = |e: ron::Error, remainder: usize| {
+        // Errors raised by serde, like unknown fields, have no position.
+        // They happen right where the deserializer stopped
+        if e.position.line == 0 {
+            let consumed = &content[..content.len() - remainder];
+            let line = consumed.matches('\n').count() + 1;
+            let col = consumed.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            (line, col, e.code)
+        } else {
+            (e.position.line, e.position.col, e.code)
+        }
+    };
+
+    // Optional fields are written without `Some(..)`
+    let options = Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+    let mut deserializer = ron::Deserializer::from_str_with_options(content, options)
+        .map_err(|e| located(e, content.len()))?;
+    let result = ConfigFile::deserialize(&mut deserializer).and_then(|config| {
+        deserializer.end()?;
+        Ok(config)
+    });
+    result.map_err(|e| located(e, deserializer.remainder().len()))
+}
@@ -0,0 +1,95 @@
+//! Watching the config file so it's loaded again when it changes
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    os::unix::io::{AsRawFd, RawFd},
+    path::PathBuf,
+};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+
+#[derive(Debug)]
+pub(crate) struct ConfigWatcher {
+    inotify: Inotify,
+    /// The config files as they were given
+    files: Vec<PathBuf>,
+    /// The watch on the directory of each config file, with the name of the file in it
+    watches: Vec<(WatchDescriptor, OsString)>,
+}
+
+impl ConfigWatcher {
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Self {
+            inotify: Inotify::init()?,
+            files: Vec::new(),
+            watches: Vec::new(),
+        })
+    }
+
+    /// Start watching the given config files instead of the ones watched until now
+    pub(crate) fn watch(&mut self, files: &[PathBuf]) {
+        if self.files == files {
+            return;
+        }
+        self.files = files.to_vec();
+        // Files in the same directory share a watch, so it may already be gone
+        for (wd, _) in self.watches.drain(..) {
+            self.inotify.rm_watch(wd).ok();
+        }
+
+        for path in files {
+            // Editors usually replace the file instead of writing to it, so the directory is watched.
+            // Symlinks are followed so the file that's actually edited is watched
+            let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            let (dir, file_name) = match (target.parent(), target.file_name()) {
+                (Some(dir), Some(file_name)) => (dir, file_name),
+                _ => continue,
+            };
+            match self
+                .inotify
+                .add_watch(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+            {
+                Ok(wd) => {
+                    log::debug!("Watching {} for changes", target.display());
+                    self.watches.push((wd, file_name.to_os_string()));
+                }
+                Err(e) => log::warn!("Failed to watch {}: {}", dir.display(), e),
+            }
+        }
+    }
+
+    /// Read all pending events, returning true if one of the config files changed
+    pub(crate) fn changed(&mut self) -> bool {
+        let mut buffer = [0; 4096];
+        let mut changed = false;
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Failed to read changes of the config: {}", e);
+                    break;
+                }
+            };
+            let mut empty = true;
+            for event in events {
+                empty = false;
+                changed |= self
+                    .watches
+                    .iter()
+                    .any(|(wd, name)| event.wd == *wd && event.name == Some(name.as_os_str()));
+            }
+            if empty {
+                break;
+            }
+        }
+        changed
+    }
+}
+
+impl AsRawFd for ConfigWatcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
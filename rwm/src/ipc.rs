@@ -0,0 +1,431 @@
+//! Non-blocking handling of the clients that connect to the socket.
+//! Every client gets its own buffers so a slow client never blocks the wm.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    time::{Duration, Instant},
+};
+
+use polling::{Event, Poller};
+
+use crate::states::WmState;
+use common::{
+    handshake, into_message, parse_handshake, parse_message, Command, EventKind, ProtocolError,
+    Request, Response, WmEvent, HANDSHAKE_LEN, MAX_MESSAGE_SIZE,
+};
+
+/// The poller key of the X connection
+pub(crate) const X_KEY: usize = 1;
+/// The poller key of the listener
+pub(crate) const LISTENER_KEY: usize = 2;
+/// The poller key of the config watcher
+pub(crate) const CONFIG_KEY: usize = 3;
+/// The poller key of the stream that wakes us up when a child exits, clients get the keys after it
+pub(crate) const CHILD_KEY: usize = 4;
+
+/// How long a client has to send a whole message
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a client has to read what we sent it
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Way more than any message, clients that send more are not playing by the rules
+const INPUT_LIMIT: usize = MAX_MESSAGE_SIZE * 2;
+/// New connections are refused while this many clients are connected
+pub(crate) const MAX_CLIENTS: usize = 64;
+
+#[derive(Debug)]
+pub(crate) struct Client {
+    stream: UnixStream,
+    /// Bytes read that don't form a whole message yet
+    input: Vec<u8>,
+    /// Bytes waiting to be written
+    output: Vec<u8>,
+    /// When the client gets dropped if it still hasn't sent a message or read our output
+    pub(crate) deadline: Option<Instant>,
+    /// If the client sent a valid handshake
+    greeted: bool,
+    /// The events the client is subscribed to, if it subscribed
+    subscription: Option<Vec<EventKind>>,
+    /// If the client should be disconnected once the output is written
+    done: bool,
+}
+
+impl Client {
+    pub(crate) fn new(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            input: Vec::new(),
+            output: Vec::new(),
+            deadline: Some(Instant::now() + READ_TIMEOUT),
+            greeted: false,
+            subscription: None,
+            done: false,
+        }
+    }
+
+    /// Read everything available, up to a bit more than `INPUT_LIMIT`.
+    /// Returns false if the client went away
+    fn read(&mut self) -> io::Result<bool> {
+        let mut buf = [0; 4096];
+        while self.input.len() <= INPUT_LIMIT {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        // The rest is read once the messages so far are handled
+        Ok(true)
+    }
+
+    /// Read and write what's possible and answer the handshake.
+    /// Returns false if the client went away
+    pub(crate) fn receive(&mut self) -> io::Result<bool> {
+        let open = self.read()?;
+        self.write()?;
+
+        if !self.greeted {
+            let result = parse_handshake(&self.input);
+            if !matches!(result, Ok(None)) {
+                // We always send our handshake so the client knows what version we speak
+                self.input.drain(..HANDSHAKE_LEN);
+                self.send(&handshake())?;
+            }
+            match result {
+                Ok(Some(())) => self.greeted = true,
+                Ok(None) => {}
+                Err(e) => {
+                    log::info!("Bad handshake: {}", e);
+                    self.done = true;
+                }
+            }
+        }
+        Ok(open)
+    }
+
+    /// Take the next whole message, if the client is in a state to send one.
+    /// Clients that don't read the replies have to wait until they do
+    pub(crate) fn next_message(&mut self) -> Option<Result<String, ProtocolError>> {
+        if !self.greeted
+            || self.done
+            || self.subscription.is_some()
+            || self.output.len() > MAX_MESSAGE_SIZE
+        {
+            return None;
+        }
+        match parse_message(&self.input) {
+            Ok(Some((msg, len))) => {
+                self.input.drain(..len);
+                Some(Ok(msg))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                // There is no way to know where the next message starts
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// If the client sent more than it could have meant to
+    pub(crate) fn flooded(&self) -> bool {
+        self.input.len() > INPUT_LIMIT
+    }
+
+    /// Write as much of the output as possible
+    fn write(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        self.update_deadline();
+        Ok(())
+    }
+
+    /// Give the client a deadline if it's in the middle of sending or reading a message.
+    /// Idle clients can stay connected for as long as they want
+    pub(crate) fn update_deadline(&mut self) {
+        let timeout = if !self.output.is_empty() {
+            WRITE_TIMEOUT
+        } else if !self.greeted || !self.input.is_empty() {
+            READ_TIMEOUT
+        } else {
+            self.deadline = None;
+            return;
+        };
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + timeout);
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(data);
+        self.write()
+    }
+
+    fn interest(&self, key: usize) -> Event {
+        if self.output.is_empty() {
+            Event::readable(key)
+        } else {
+            Event::all(key)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Ipc {
+    listener: UnixListener,
+    clients: HashMap<usize, Client>,
+    next_key: usize,
+}
+
+impl Ipc {
+    /// Start handling clients from the listener. The listener needs to be non blocking
+    pub(crate) fn new(listener: UnixListener) -> Self {
+        Self {
+            listener,
+            clients: HashMap::new(),
+            next_key: CHILD_KEY + 1,
+        }
+    }
+
+    pub(crate) fn listener(&self) -> &UnixListener {
+        &self.listener
+    }
+
+    /// How long the poller can wait before some client has to be dropped
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.clients
+            .values()
+            .filter_map(|client| client.deadline)
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Accept every pending connection
+    pub(crate) fn accept(&mut self, poller: &Poller) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _addr)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log::warn!("Failed to accept client: {}", e);
+                    break;
+                }
+            };
+
+            if self.clients.len() >= MAX_CLIENTS {
+                log::warn!("Refusing client, {} are connected already", MAX_CLIENTS);
+                continue;
+            }
+
+            let key = self.next_key;
+            self.next_key += 1;
+            if let Err(e) = stream
+                .set_nonblocking(true)
+                .and_then(|_| poller.add(&stream, Event::readable(key)))
+            {
+                log::warn!("Failed to set up client: {}", e);
+                continue;
+            }
+            log::trace!("Accepted client #{}", key);
+            self.clients.insert(key, Client::new(stream));
+        }
+    }
+
+    /// Handle a client the poller said is ready, running any whole message it sent
+    pub(crate) fn on_ready(&mut self, poller: &Poller, key: usize, wm_state: &mut WmState) {
+        let mut client = match self.clients.get_mut(&key) {
+            Some(client) => client,
+            None => return,
+        };
+
+        let open = match client.receive() {
+            Ok(open) => open,
+            Err(e) => {
+                log::info!("Dropping client #{}: {}", key, e);
+                return self.drop_client(poller, key);
+            }
+        };
+
+        // Run every whole message, a client can send any number of them until it subscribes
+        while let Some(msg) = client.next_message() {
+            let reply = match msg {
+                Ok(msg) => self.run(key, &msg, wm_state),
+                Err(e) => Response::Error(e.to_string()),
+            };
+            self.reply(key, reply);
+            client = match self.clients.get_mut(&key) {
+                Some(client) => client,
+                None => return,
+            };
+        }
+        if client.flooded() {
+            log::info!("Dropping client #{}: sent too much data", key);
+            return self.drop_client(poller, key);
+        }
+        client.update_deadline();
+
+        if !open {
+            // The client went away, but it may still read what's left of the output
+            log::trace!("Client #{} closed the connection", key);
+            if let Some(client) = self.clients.get_mut(&key) {
+                client.subscription = None;
+                client.done = true;
+            }
+        }
+        self.update(poller, key);
+    }
+
+    /// Run a message from the given client
+    fn run(&mut self, key: usize, msg: &str, wm_state: &mut WmState) -> Response {
+        log::trace!("Got command from client #{}: {}", key, msg);
+        let cmd = match serde_json::from_str::<Request>(msg) {
+            Ok(Request::Command(cmd)) => cmd,
+            Ok(Request::Batch(cmds)) => return wm_state.handle_batch(cmds),
+            Err(e) => return Response::Error(format!("Invalid command: {}", e)),
+        };
+
+        if let Command::Subscribe { events } = &cmd {
+            // Existing subscribers need to hear about what happened before the new one joined
+            if self.has_subscribers() {
+                let pending = wm_state.take_events();
+                self.broadcast(&pending);
+            } else {
+                wm_state.take_events();
+            }
+            if let Some(client) = self.clients.get_mut(&key) {
+                client.subscription = Some(events.clone());
+            }
+        }
+
+        Response::from_result(wm_state.handle_command(cmd))
+    }
+
+    /// Queue the reply to the client
+    fn reply(&mut self, key: usize, reply: Response) {
+        let client = match self.clients.get_mut(&key) {
+            Some(client) => client,
+            None => return,
+        };
+        if matches!(reply, Response::Error(_)) {
+            client.subscription = None;
+        }
+
+        let reply = into_message(&reply).unwrap_or_else(|e| {
+            into_message(Response::Error(format!(
+                "Failed to send the reply: {:#}",
+                e
+            )))
+            .unwrap()
+        });
+        if let Err(e) = client.send(&reply) {
+            log::info!("Failed to reply to client #{}: {}", key, e);
+            client.output.clear();
+            client.done = true;
+        }
+    }
+
+    /// Drop the client if it's done, otherwise update what we wait for from it
+    fn update(&mut self, poller: &Poller, key: usize) {
+        let client = match self.clients.get(&key) {
+            Some(client) => client,
+            None => return,
+        };
+        if client.done && client.output.is_empty() {
+            return self.drop_client(poller, key);
+        }
+        if let Err(e) = poller.modify(&client.stream, client.interest(key)) {
+            log::info!("Dropping client #{}: {}", key, e);
+            self.drop_client(poller, key);
+        }
+    }
+
+    fn drop_client(&mut self, poller: &Poller, key: usize) {
+        if let Some(client) = self.clients.remove(&key) {
+            poller.delete(&client.stream).ok();
+        }
+    }
+
+    /// Drop the clients that took too long to send a message or read what we sent
+    pub(crate) fn drop_expired(&mut self, poller: &Poller) {
+        let now = Instant::now();
+        let expired = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in expired {
+            log::info!("Client #{} timed out", key);
+            self.drop_client(poller, key);
+        }
+    }
+
+    pub(crate) fn has_subscribers(&self) -> bool {
+        self.clients
+            .values()
+            .any(|client| client.subscription.is_some())
+    }
+
+    /// Send the events that happened since the last call to the subscribed clients
+    pub(crate) fn notify_subscribers(&mut self, poller: &Poller, wm_state: &mut WmState) {
+        if !self.has_subscribers() {
+            return;
+        }
+
+        let events = wm_state.take_events();
+        if events.is_empty() {
+            return;
+        }
+        self.broadcast(&events);
+
+        let keys = self.clients.keys().copied().collect::<Vec<_>>();
+        for key in keys {
+            self.update(poller, key);
+        }
+    }
+
+    /// Queue the events as lines of JSON to the subscribers that want them
+    fn broadcast(&mut self, events: &[WmEvent]) {
+        for client in self.clients.values_mut() {
+            let wanted = match &client.subscription {
+                Some(wanted) => wanted,
+                None => continue,
+            };
+
+            for event in events.iter() {
+                if !wanted.is_empty() && !wanted.contains(&event.kind()) {
+                    continue;
+                }
+                if let Ok(mut line) = serde_json::to_string(event) {
+                    line.push('\n');
+                    client.output.extend_from_slice(line.as_bytes());
+                }
+            }
+
+            if client.output.len() > MAX_MESSAGE_SIZE {
+                // It doesn't keep up with the events, so it gets dropped on the next update
+                log::info!("A subscriber fell behind, dropping it");
+                client.subscription = None;
+                client.done = true;
+                client.output.clear();
+            } else if client.write().is_err() {
+                client.done = true;
+                client.output.clear();
+            }
+        }
+    }
+}
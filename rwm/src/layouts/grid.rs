@@ -19,7 +19,7 @@ pub(crate) fn update(
     // TODO bar space
     let mut windows = focus
         .iter_on_tags_mut(tags)
-        .filter(|win| !win.floating)
+        .filter(|win| win.is_tiled())
         .collect::<Vec<_>>();
 
     let width = rect.width as u32;
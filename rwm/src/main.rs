@@ -1,17 +1,22 @@
+mod atoms;
 mod color;
 mod config;
+mod config_watcher;
+mod ipc;
 mod layouts;
 mod mod_mask;
 mod monitors_history;
+mod opt;
 mod rect;
 mod spawn_rule;
 mod states;
+mod subscriptions;
 #[cfg(test)]
 mod tests;
 mod utils;
 mod windows_history;
 
-use std::{io::Write, net::Shutdown, os::unix::net::UnixListener, path::PathBuf};
+use std::fs;
 
 use {
     anyhow::{bail, Context},
@@ -26,21 +31,7 @@ use {
     },
 };
 
-use common::into_message;
-use {config::Config, states::WmState};
-
-#[derive(StructOpt, Debug)]
-struct Opt {
-    /// Optional path to a config file
-    #[structopt(short, long)]
-    config: Option<PathBuf>,
-    /// The directory to put logs in, defaults to $XDG_CONFIG_HOME/rwm/logs
-    #[structopt(long)]
-    logs_dir: Option<PathBuf>,
-    /// Prints the default configuration in stdout and exits
-    #[structopt(short, long)]
-    print: bool,
-}
+use {config::Config, config_watcher::ConfigWatcher, ipc::Ipc, opt::Opt, states::WmState};
 
 fn try_become_wm(conn: &RustConnection, screen: &Screen) -> Result<(), ReplyError> {
     let change = ChangeWindowAttributesAux::default().event_mask(
@@ -64,6 +55,32 @@ fn main() -> anyhow::Result<()> {
         );
         return Ok(());
     }
+    if let Some(path) = options.check_config {
+        match Config::parse(&path) {
+            Ok(_) => println!("{} is valid", path.display()),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if options.init_config {
+        // Never the system config dirs, those are for the whole system
+        let path = Config::user_path().context(
+            "Failed to find your config directory, set $XDG_CONFIG_HOME or $HOME to create one",
+        )?;
+        if path.exists() {
+            bail!("`{}` already exists", path.display());
+        }
+        let config = ron::ser::to_string_pretty(&Config::default(), Default::default())?;
+        fs::create_dir_all(path.parent().unwrap())
+            .context("Failed to create the config directory")?;
+        fs::write(&path, config)
+            .with_context(|| format!("Failed to write `{}`", path.display()))?;
+        println!("Wrote the default config to {}", path.display());
+        return Ok(());
+    }
 
     let logger = utils::init_logging(&options.logs_dir);
     if logger.is_err() {
@@ -92,18 +109,34 @@ fn main() -> anyhow::Result<()> {
     // We are the window manager!
 
     let config = match options.config {
-        Some(path) => Config::from_file(path)?,
-        None => {
-            log::info!("Using default config");
-            Config::default()
+        Some(path) => {
+            log::info!("Using the config file given: {}", path.display());
+            Config::from_file(path)?
         }
+        None => match Config::find() {
+            Some(path) => {
+                log::info!("Using the config file found: {}", path.display());
+                Config::from_file(path.clone()).unwrap_or_else(|e| {
+                    // It's still watched, so fixing it is enough to load it
+                    log::error!("{:#}. Using the default config until it's fixed", e);
+                    Config {
+                        files: vec![path.clone()],
+                        path: Some(path),
+                        ..Default::default()
+                    }
+                })
+            }
+            None => {
+                log::info!(
+                    "No config file in {:?}, using the default config",
+                    Config::search_paths()
+                );
+                Config::default()
+            }
+        },
     };
     log::debug!("Using config {:#?}", config);
 
-    if config.layouts.is_empty() {
-        bail!("There needs to be at least one layout in the config");
-    }
-
     // Let's get the actual cursor handle
     let cursor_handle = cursor_handle.reply().context("An X11 error occurred")?;
     // Set the cursor to the classic left pointer
@@ -114,22 +147,39 @@ fn main() -> anyhow::Result<()> {
     )
     .context("An X11 connection error occurred")?;
 
-    let mut wm_state = WmState::new(&conn, screen_num, config, cursor_handle)?;
+    let socket = common::socket_path(options.socket);
+
+    let mut wm_state = WmState::new(&conn, screen_num, config, cursor_handle, socket.clone())?;
     wm_state
         .scan_windows()
         .context("Error while looking for pre-existing windows")?;
 
-    let listener = UnixListener::bind("/tmp/rwm.sock").context("Failed to connect to socket")?;
+    let listener = utils::bind_socket(&socket)?;
+    log::info!("Listening on {}", socket.display());
     listener
         .set_nonblocking(true)
         .context("Couldn't set socket to non blocking")?;
+    let mut ipc = Ipc::new(listener);
+    // Set up before anything is spawned, so no child can exit unnoticed
+    let mut child_exits = utils::child_exits().context("Failed to handle SIGCHLD")?;
+    // Started once the socket is up, so things like bars can talk to the wm right away
+    let autostart = wm_state.config.autostart.clone();
+    wm_state.spawn_all(&autostart);
 
     let poller = polling::Poller::new().unwrap();
     poller
-        .add(conn.stream(), polling::Event::readable(1))
+        .add(conn.stream(), polling::Event::readable(ipc::X_KEY))
+        .context("epoll add failed")?;
+    poller
+        .add(ipc.listener(), polling::Event::readable(ipc::LISTENER_KEY))
+        .context("epoll add failed")?;
+    let mut config_watcher = ConfigWatcher::new().context("Failed to set up inotify")?;
+    config_watcher.watch(&wm_state.config.files);
+    poller
+        .add(&config_watcher, polling::Event::readable(ipc::CONFIG_KEY))
         .context("epoll add failed")?;
     poller
-        .add(&listener, polling::Event::readable(2))
+        .add(&child_exits, polling::Event::readable(ipc::CHILD_KEY))
         .context("epoll add failed")?;
     // events from poller go here
     let mut events = Vec::new();
@@ -138,24 +188,11 @@ fn main() -> anyhow::Result<()> {
     // Main loop
     while wm_state.running {
         wm_state.conn.flush().context("Error talking to X server")?;
-        if poller.wait(&mut events, None).is_err() {
+        // Wake up in time to drop clients that take too long
+        if poller.wait(&mut events, ipc.timeout()).is_err() {
             // ToDo It's possible I should handle and exit on some errors
             continue;
         }
-        // We just want to iterate and modify them so we wait for the next event as well
-        // By default once it gets the first event from a source it doesn't wait for another one again..
-        // We use drain() because we want to clear the event for the next to go in
-        for ev in events.drain(..) {
-            if ev.key == 1 {
-                poller
-                    .modify(conn.stream(), polling::Event::readable(1))
-                    .context("Error setting the interest for new events")?;
-            } else if ev.key == 2 {
-                poller
-                    .modify(&listener, polling::Event::readable(2))
-                    .context("Error setting the interest for new events")?;
-            }
-        }
 
         while let Some(event) = wm_state
             .conn
@@ -174,15 +211,50 @@ fn main() -> anyhow::Result<()> {
             wm_state.handle_event(event)?;
         }
 
-        if let Ok((mut stream, _adr)) = listener.accept() {
-            let reply = match wm_state.handle_client(&mut stream) {
-                Ok(msg) => into_message(msg),
-                Err(e) => into_message(format!("{:?}", e)),
+        // We just want to iterate and modify them so we wait for the next event as well
+        // By default once it gets the first event from a source it doesn't wait for another one again..
+        // We use drain() because we want to clear the event for the next to go in
+        for ev in events.drain(..) {
+            match ev.key {
+                ipc::X_KEY => poller
+                    .modify(conn.stream(), polling::Event::readable(ipc::X_KEY))
+                    .context("Error setting the interest for new events")?,
+                ipc::LISTENER_KEY => {
+                    poller
+                        .modify(ipc.listener(), polling::Event::readable(ipc::LISTENER_KEY))
+                        .context("Error setting the interest for new events")?;
+                    ipc.accept(&poller);
+                }
+                ipc::CONFIG_KEY => {
+                    poller
+                        .modify(&config_watcher, polling::Event::readable(ipc::CONFIG_KEY))
+                        .context("Error setting the interest for new events")?;
+                    if config_watcher.changed() {
+                        if wm_state.config.is_saved() {
+                            // Our own write, loading it would only run `on_reload` again
+                            log::debug!("The config file was saved by `config save`");
+                        } else {
+                            log::info!("The config file changed, loading it again");
+                            wm_state.load_config(None).ok();
+                        }
+                    }
+                }
+                ipc::CHILD_KEY => {
+                    poller
+                        .modify(&child_exits, polling::Event::readable(ipc::CHILD_KEY))
+                        .context("Error setting the interest for new events")?;
+                    // The children are reaped below, like after any other event
+                    utils::drain(&mut child_exits);
+                }
+                key => ipc.on_ready(&poller, key, &mut wm_state),
             }
-            .unwrap_or_else(|_| into_message("Failed to serialise original message").unwrap());
-            stream.write(reply.as_ref()).ok(); // .ok() is used to basically just ignore the result
-            stream.shutdown(Shutdown::Both).ok();
-        };
+        }
+
+        wm_state.reap_children();
+        ipc.drop_expired(&poller);
+        ipc.notify_subscribers(&poller, &mut wm_state);
+        // A client may have loaded another config file, or the config may include other files now
+        config_watcher.watch(&wm_state.config.files);
     }
 
     utils::clean_up().context("Failed to clean up.")
@@ -3,7 +3,7 @@ use std::collections::VecDeque;
 use x11rb::protocol::xproto::Window;
 
 use crate::states::mon_state::Monitor;
-use common::Direction;
+use common::{Direction, MonitorTarget};
 
 /// A data structure that holds the history of monitors
 #[derive(Debug)]
@@ -36,6 +36,11 @@ impl MonitorsHistory {
         self.monitors.iter_mut()
     }
 
+    /// The index of the focused monitor
+    pub(crate) fn cur_index(&self) -> usize {
+        self.cur
+    }
+
     pub(crate) fn cur(&self) -> &Monitor {
         &self.monitors[self.cur]
     }
@@ -44,6 +49,70 @@ impl MonitorsHistory {
         &mut self.monitors[self.cur]
     }
 
+    /// Replace the monitors with the given ones.
+    /// Monitors that still exist (matched by name) keep their state and only get the new geometry,
+    /// while the windows of monitors that disappeared are moved to the focused monitor.
+    ///
+    /// # Panics
+    /// Panics if there is no monitor given
+    pub(crate) fn replace(&mut self, new: Vec<Monitor>) {
+        assert!(!new.is_empty());
+        let cur_id = self.cur().id;
+
+        let mut old = self.monitors.drain(..).collect::<Vec<_>>();
+        for mon in new {
+            match old.iter().position(|old_mon| old_mon.name == mon.name) {
+                Some(pos) => {
+                    let mut kept = old.remove(pos);
+                    kept.rect = mon.rect;
+                    self.monitors.push_back(kept);
+                }
+                None => {
+                    log::info!("Monitor {} was added", mon.name);
+                    self.monitors.push_back(mon);
+                }
+            }
+        }
+
+        self.cur = self
+            .monitors
+            .iter()
+            .position(|mon| mon.id == cur_id)
+            .unwrap_or(0);
+
+        for gone in old {
+            log::info!("Monitor {} was removed", gone.name);
+            self.cur_mut().adopt(gone);
+        }
+    }
+
+    /// Get the monitor in the given position
+    pub(crate) fn get(&self, index: usize) -> Option<&Monitor> {
+        self.monitors.get(index)
+    }
+
+    /// Get the monitor in the given position
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut Monitor> {
+        self.monitors.get_mut(index)
+    }
+
+    /// Exchange two windows between two different monitors, each one taking the place of the
+    /// other in the list of windows.
+    /// The tags stay in place, so both windows are visible if they were before.
+    ///
+    /// # Panics
+    /// Panics if the monitors are the same or if any of the positions doesn't exist
+    pub(crate) fn swap_windows(&mut self, a: (usize, usize), b: (usize, usize)) {
+        assert_ne!(a.0, b.0);
+        let ((low_mon, low_win), (high_mon, high_win)) = if a.0 < b.0 { (a, b) } else { (b, a) };
+
+        let (low, high) = self.monitors.make_contiguous().split_at_mut(high_mon);
+        let low = low[low_mon].windows.get_mut(low_win).unwrap();
+        let high = high[0].windows.get_mut(high_win).unwrap();
+        std::mem::swap(low, high);
+        std::mem::swap(&mut low.tags, &mut high.tags);
+    }
+
     /// Focus the monitor that contains the given window
     ///
     /// # Panics
@@ -80,23 +149,41 @@ impl MonitorsHistory {
         &mut self.monitors[old]
     }
 
-    pub(crate) fn focus(&mut self, dir: Direction) {
-        self.cur = match dir {
-            Direction::Up => {
+    /// Find the index of the monitor the target points to
+    pub(crate) fn position(&self, target: &MonitorTarget) -> Option<usize> {
+        match target {
+            MonitorTarget::Direction(Direction::Up) => {
                 if self.cur == 0 {
-                    self.monitors.len() - 1
+                    Some(self.monitors.len() - 1)
                 } else {
-                    self.cur - 1
+                    Some(self.cur - 1)
                 }
             }
-            Direction::Down => {
+            MonitorTarget::Direction(Direction::Down) => {
                 if self.cur == self.monitors.len() - 1 {
-                    0
+                    Some(0)
                 } else {
-                    self.cur + 1
+                    Some(self.cur + 1)
                 }
             }
-        };
-        log::debug!("Focusing monitor #{}", self.cur);
+            MonitorTarget::Spatial(dir) => self
+                .cur()
+                .rect
+                .closest(*dir, self.monitors.iter().map(|mon| &mon.rect)),
+            MonitorTarget::Index(index) => (*index < self.monitors.len()).then_some(*index),
+            MonitorTarget::Name(name) => self.monitors.iter().position(|mon| &mon.name == name),
+        }
+    }
+
+    /// Focus the monitor the target points to, returning false if there is no such monitor
+    pub(crate) fn focus(&mut self, target: &MonitorTarget) -> bool {
+        match self.position(target) {
+            Some(index) => {
+                self.cur = index;
+                log::debug!("Focusing monitor #{} ({})", self.cur, self.cur().name);
+                true
+            }
+            None => false,
+        }
     }
 }
@@ -0,0 +1,27 @@
+// The command line options, also included by build.rs to generate the man page
+
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub(crate) struct Opt {
+    /// Path to a config file, defaults to $XDG_CONFIG_HOME/rwm/config.ron or /etc/xdg/rwm/config.ron
+    #[structopt(short, long)]
+    pub(crate) config: Option<PathBuf>,
+    /// The directory to put logs in, defaults to $XDG_CONFIG_HOME/rwm/logs
+    #[structopt(long)]
+    pub(crate) logs_dir: Option<PathBuf>,
+    /// Prints the default configuration in stdout and exits
+    #[structopt(short, long)]
+    pub(crate) print: bool,
+    /// Writes the default configuration to $XDG_CONFIG_HOME/rwm/config.ron and exits
+    #[structopt(long)]
+    pub(crate) init_config: bool,
+    /// Checks a config file for errors and exits
+    #[structopt(long, value_name = "path")]
+    pub(crate) check_config: Option<PathBuf>,
+    /// Path to the socket clients connect to, defaults to $XDG_RUNTIME_DIR/rwm-<display>.sock
+    #[structopt(long, env = "RWM_SOCKET", hide_env_values = true)]
+    pub(crate) socket: Option<PathBuf>,
+}
@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use common::SpatialDirection;
+
+#[derive(Debug, Clone, Copy)]
 pub(crate) struct Rect {
     pub(crate) x: i16,
     pub(crate) y: i16,
@@ -22,6 +24,61 @@ impl Rect {
             && y >= self.y
             && y as i32 <= self.y as i32 + self.height as i32
     }
+
+    fn center(&self) -> (i32, i32) {
+        (
+            self.x as i32 + self.width as i32 / 2,
+            self.y as i32 + self.height as i32 / 2,
+        )
+    }
+
+    /// Find the index of the rect that is closest to this one in the given direction.
+    /// Rects that line up with this one (overlap on the other axis) are preferred over the rest,
+    /// so moving left from a tall window picks the window next to it and not a diagonal one.
+    pub(crate) fn closest<'a>(
+        &self,
+        dir: SpatialDirection,
+        others: impl IntoIterator<Item = &'a Rect>,
+    ) -> Option<usize> {
+        let (cx, cy) = self.center();
+        let overlaps = |start: i16, len: u16, other_start: i16, other_len: u16| {
+            (start as i32) < other_start as i32 + other_len as i32
+                && (other_start as i32) < start as i32 + len as i32
+        };
+
+        others
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, other)| {
+                let (ox, oy) = other.center();
+                // (distance on the axis of the direction, distance on the other axis, if they overlap)
+                let (main, cross, lined_up) = match dir {
+                    SpatialDirection::Left => (
+                        cx - ox,
+                        (cy - oy).abs(),
+                        overlaps(self.y, self.height, other.y, other.height),
+                    ),
+                    SpatialDirection::Right => (
+                        ox - cx,
+                        (cy - oy).abs(),
+                        overlaps(self.y, self.height, other.y, other.height),
+                    ),
+                    SpatialDirection::Up => (
+                        cy - oy,
+                        (cx - ox).abs(),
+                        overlaps(self.x, self.width, other.x, other.width),
+                    ),
+                    SpatialDirection::Down => (
+                        oy - cy,
+                        (cx - ox).abs(),
+                        overlaps(self.x, self.width, other.x, other.width),
+                    ),
+                };
+                (main > 0).then_some((i, (!lined_up, main, cross)))
+            })
+            .min_by_key(|(_, key)| *key)
+            .map(|(i, _)| i)
+    }
 }
 
 impl From<(i16, i16, u16, u16)> for Rect {
@@ -1,12 +1,224 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, convert::TryFrom};
+
+use {
+    regex::Regex,
+    serde::{Deserialize, Serialize},
+};
 
 use common::TagId;
 
-/// A simple spawn rule for new windows
+/// The `_NET_WM_WINDOW_TYPE_*` types, lowercase and without the prefix
+pub(crate) const WINDOW_TYPES: [&str; 14] = [
+    "desktop",
+    "dock",
+    "toolbar",
+    "menu",
+    "utility",
+    "splash",
+    "dialog",
+    "dropdown_menu",
+    "popup_menu",
+    "tooltip",
+    "notification",
+    "combo",
+    "dnd",
+    "normal",
+];
+
+/// A rule for new windows
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) enum SpawnRule {
-    // match against WM_CLASS
+    // match against both parts of WM_CLASS
     ClassName(String, Vec<TagId>),
-    // match against WM_NAME
+    // match against the whole title
     WmName(String, Vec<TagId>),
+    /// Written as `Rule(class: "mpv", floating: true)`.
+    /// Every matcher that's set has to match for the actions to apply
+    Rule {
+        /// The class, the second part of WM_CLASS
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        class: Option<String>,
+        /// The instance, the first part of WM_CLASS
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        instance: Option<String>,
+        /// A regex found anywhere in the title
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<TitleRegex>,
+        /// WM_WINDOW_ROLE
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        role: Option<String>,
+        /// One of `WINDOW_TYPES`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window_type: Option<String>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tags: Option<Vec<TagId>>,
+        /// The name or the index of the monitor
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        monitor: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        floating: Option<bool>,
+        /// Relative to the monitor, makes the window floating
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        geometry: Option<Geometry>,
+        /// Put the window in the middle of the monitor, makes it floating
+        #[serde(default, skip_serializing_if = "is_false")]
+        centre: bool,
+        #[serde(default, skip_serializing_if = "is_false")]
+        fullscreen: bool,
+        /// Show the window on every tag
+        #[serde(default, skip_serializing_if = "is_false")]
+        sticky: bool,
+        /// Don't give focus to the window when it shows up
+        #[serde(default, skip_serializing_if = "is_false")]
+        no_focus: bool,
+    },
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl SpawnRule {
+    pub(crate) fn matches(&self, props: &WindowProps) -> bool {
+        match self {
+            Self::ClassName(name, _) => props.instance == *name || props.class == *name,
+            Self::WmName(name, _) => props.title == *name,
+            Self::Rule {
+                class,
+                instance,
+                title,
+                role,
+                window_type,
+                ..
+            } => {
+                class.as_ref().map_or(true, |class| props.class == *class)
+                    && instance
+                        .as_ref()
+                        .map_or(true, |instance| props.instance == *instance)
+                    && title
+                        .as_ref()
+                        .map_or(true, |title| title.0.is_match(&props.title))
+                    && role.as_ref().map_or(true, |role| props.role == *role)
+                    && window_type
+                        .as_ref()
+                        .map_or(true, |kind| props.window_types.contains(kind))
+            }
+        }
+    }
+
+    /// If the rule looks at the title, which can change after the window shows up
+    pub(crate) fn uses_title(&self) -> bool {
+        matches!(self, Self::WmName(..) | Self::Rule { title: Some(_), .. })
+    }
+
+    /// If there's nothing to match on, which is most likely a mistake
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Self::ClassName(..) | Self::WmName(..) => false,
+            Self::Rule {
+                class,
+                instance,
+                title,
+                role,
+                window_type,
+                ..
+            } => {
+                class.is_none()
+                    && instance.is_none()
+                    && title.is_none()
+                    && role.is_none()
+                    && window_type.is_none()
+            }
+        }
+    }
+
+    /// Add what the rule does on top of what earlier rules did
+    pub(crate) fn apply(&self, actions: &mut RuleActions) {
+        match self {
+            Self::ClassName(_, tag_ids) | Self::WmName(_, tag_ids) => {
+                actions.tags = Some(tag_ids.iter().copied().collect());
+            }
+            Self::Rule {
+                tags,
+                monitor,
+                floating,
+                geometry,
+                centre,
+                fullscreen,
+                sticky,
+                no_focus,
+                ..
+            } => {
+                if let Some(tags) = tags {
+                    actions.tags = Some(tags.iter().copied().collect());
+                }
+                if monitor.is_some() {
+                    actions.monitor = monitor.clone();
+                }
+                if floating.is_some() {
+                    actions.floating = *floating;
+                }
+                if geometry.is_some() {
+                    actions.geometry = *geometry;
+                }
+                actions.centre |= centre;
+                actions.fullscreen |= fullscreen;
+                actions.sticky |= sticky;
+                actions.no_focus |= no_focus;
+            }
+        }
+    }
+}
+
+/// What the matching rules do to a window, later rules override earlier ones
+#[derive(Debug, Default)]
+pub(crate) struct RuleActions {
+    pub(crate) tags: Option<HashSet<TagId>>,
+    pub(crate) monitor: Option<String>,
+    pub(crate) floating: Option<bool>,
+    pub(crate) geometry: Option<Geometry>,
+    pub(crate) centre: bool,
+    pub(crate) fullscreen: bool,
+    pub(crate) sticky: bool,
+    pub(crate) no_focus: bool,
+}
+
+/// The properties of a window the rules match against
+#[derive(Debug, Default)]
+pub(crate) struct WindowProps {
+    pub(crate) instance: String,
+    pub(crate) class: String,
+    pub(crate) title: String,
+    pub(crate) role: String,
+    pub(crate) window_types: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Geometry {
+    pub(crate) x: i16,
+    pub(crate) y: i16,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+}
+
+/// A regex that's read and written as a string
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct TitleRegex(Regex);
+
+impl TryFrom<String> for TitleRegex {
+    type Error = regex::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Regex::new(&value).map(Self)
+    }
+}
+
+impl From<TitleRegex> for String {
+    fn from(regex: TitleRegex) -> Self {
+        regex.0.as_str().to_string()
+    }
 }
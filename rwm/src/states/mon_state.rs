@@ -1,11 +1,16 @@
 use std::sync::atomic::AtomicU32;
 
-use x11rb::{errors::ReplyOrIdError, protocol::xproto::Window, rust_connection::RustConnection};
+use x11rb::{
+    errors::ReplyOrIdError,
+    protocol::xproto::{ConfigureWindowAux, ConnectionExt, StackMode, Window},
+    rust_connection::RustConnection,
+};
 
 use crate::{
     config::Config,
     rect::Rect,
     states::WinState,
+    utils,
     {layouts::LayoutType, states::TagState, windows_history::WindowsHistory},
 };
 use common::{Direction, LayoutSubcommand, TagId};
@@ -16,6 +21,8 @@ static NEXT_ID: AtomicU32 = AtomicU32::new(0);
 pub(crate) struct Monitor {
     /// A randomly generated number
     pub(crate) id: u32,
+    /// The name RandR gives to this monitor, usually the name of the output (ex `DP-1`)
+    pub(crate) name: String,
     /// The window ids of the windows currently in this monitor
     pub(crate) windows: WindowsHistory,
     /// The tags that are currently visible
@@ -29,7 +36,7 @@ pub(crate) struct Monitor {
 }
 
 impl Monitor {
-    pub(crate) fn new(config: &Config, rect: Rect) -> Self {
+    pub(crate) fn new(config: &Config, name: String, rect: Rect) -> Self {
         let def_layout = config.layouts[0];
         // tags are 1-9 and the default is 1
         let mut tags: Vec<TagState> = (1..=9)
@@ -39,6 +46,7 @@ impl Monitor {
 
         Self {
             id: NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            name,
             windows: WindowsHistory::new(),
             tags,
             layout: def_layout,
@@ -68,11 +76,42 @@ impl Monitor {
         self.windows.shift(dir, self.tags.as_slice());
     }
 
+    /// Swap the current window with the master, returning the window that should get focus
+    pub(crate) fn zoom(&mut self) -> Option<Window> {
+        self.windows.zoom(self.tags.as_slice())
+    }
+
+    pub(crate) fn swap_focused(&mut self, index: usize) -> bool {
+        self.windows.swap_focused(index, self.tags.as_slice())
+    }
+
     /// Return the window that gets forgotten and the window that should get focus
     pub(crate) fn forget(&mut self, window: Window) -> (Option<WinState>, Option<&WinState>) {
         self.windows.forget(window, self.tags.as_slice())
     }
 
+    /// Take over the windows of a monitor that doesn't exist anymore.
+    /// Tags that have no windows on this monitor also take the layout they had on the old one
+    pub(crate) fn adopt(&mut self, other: Monitor) {
+        for old_tag in other.tags.iter() {
+            let has_windows = self
+                .windows
+                .iter()
+                .any(|win| win.tags.contains(&old_tag.id));
+            if has_windows {
+                continue;
+            }
+            if let Some(tag) = self.tags.iter_mut().find(|tag| **tag == old_tag.id) {
+                tag.layout = old_tag.layout;
+            }
+        }
+
+        self.windows.append(other.windows);
+        if self.windows.get_focused().is_none() {
+            self.reset_focus();
+        }
+    }
+
     pub(crate) fn change_layout(&mut self, dir: &LayoutSubcommand, layouts: &[LayoutType]) {
         self.layout = match dir {
             LayoutSubcommand::Next => self.layout.next(layouts),
@@ -100,7 +139,23 @@ impl Monitor {
             &self.rect,
             config.border_width,
             config.gap,
-        )
+        )?;
+
+        for win in self.windows.iter().filter(|win| win.fullscreen) {
+            if utils::is_visible(win, &self.tags) {
+                conn.configure_window(
+                    win.id,
+                    &ConfigureWindowAux::new()
+                        .x(self.rect.x as i32)
+                        .y(self.rect.y as i32)
+                        .width(self.rect.width as u32)
+                        .height(self.rect.height as u32)
+                        .border_width(0)
+                        .stack_mode(StackMode::ABOVE),
+                )?;
+            }
+        }
+        Ok(())
     }
 
     /// Find the first visible window in the tags and set it as focused
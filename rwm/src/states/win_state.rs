@@ -2,7 +2,7 @@ use std::collections::HashSet;
 
 use x11rb::protocol::xproto::*;
 
-use crate::states::TagState;
+use crate::{rect::Rect, states::TagState};
 use common::TagId;
 
 #[derive(Debug, PartialEq)]
@@ -16,6 +16,12 @@ pub struct WinState {
     pub(crate) tags: HashSet<TagId>,
     /// If true then ignoring when tilling windows
     pub(crate) floating: bool,
+    /// Covers its whole monitor, above the other windows
+    pub(crate) fullscreen: bool,
+    /// The rules looking at the title that matched it last time, by their index in the config
+    pub(crate) title_rules: Vec<usize>,
+    /// The depth of its visual, 32 if it has an alpha channel
+    pub(crate) depth: u8,
 }
 
 impl WinState {
@@ -32,6 +38,18 @@ impl WinState {
                 .map(|tag_state| tag_state.id)
                 .collect(),
             floating: false,
+            fullscreen: false,
+            title_rules: Vec::new(),
+            depth: geom.depth,
         }
     }
+
+    /// If the layout places this window
+    pub(crate) fn is_tiled(&self) -> bool {
+        !self.floating && !self.fullscreen
+    }
+
+    pub(crate) fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
 }
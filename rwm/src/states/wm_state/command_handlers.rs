@@ -1,11 +1,18 @@
 use {
-    anyhow::{Context, Result},
-    x11rb::protocol::xproto::ConnectionExt,
+    anyhow::{bail, Context, Result},
+    regex::Regex,
+    serde_json::{json, Value},
+    x11rb::protocol::xproto::{ConnectionExt, Window},
 };
 
-use crate::{utils::visible, WmState};
+use crate::{
+    states::{Monitor, WinState},
+    utils::{self, visible},
+    WmState,
+};
 use common::{
-    Command, Destination, Direction, MonitorSubcommand, TagSubcommand, WindowSubcommand,
+    Command, Destination, Direction, FocusDirection, MonitorSubcommand, MonitorTarget,
+    QuerySubcommand, Response, TagSubcommand, WindowCommand, WindowSelector, WindowSubcommand,
     WindowToggle,
 };
 
@@ -14,7 +21,7 @@ impl<'a> WmState<'a> {
         match sub {
             TagSubcommand::Toggle { tag_id } => {
                 let one_vis = visible(&self.monitors.cur().tags).len() == 1;
-                if let Some(mut tag_state) = self
+                if let Some(tag_state) = self
                     .monitors
                     .cur_mut()
                     .tags
@@ -38,19 +45,82 @@ impl<'a> WmState<'a> {
             .with_context(|| format!("Failed to update windows after `Tag({:?})`", sub))
     }
 
-    pub(crate) fn on_window_cmd(&mut self, sub: WindowSubcommand) -> Result<()> {
-        let focused_window = self.monitors.cur().windows.get_focused();
-        if focused_window.is_none() {
+    pub(crate) fn on_window_cmd(&mut self, cmd: WindowCommand) -> Result<()> {
+        let WindowCommand { selector, action } = cmd;
+        // `focus next`/`prev` with a selector goes through the matching windows, so the selector
+        // picks the window to focus on its own
+        let cycle = match &action {
+            WindowSubcommand::Focus {
+                direction: FocusDirection::List(dir),
+            } if !selector.is_empty() => Some(*dir),
+            _ => None,
+        };
+        let (mon_index, id) = match self.select_window(&selector, cycle)? {
+            Some(target) => target,
             // There is no focused window so just do nothing
-            return Ok(());
+            None if selector.is_empty() => return Ok(()),
+            None => bail!("No window matches {:?}", selector),
+        };
+        if cycle.is_some() {
+            self.focus(id)?;
+            return self.update_windows().context(format!(
+                "Failed to update windows after `Window({:?})`",
+                action
+            ));
         }
-        let focused_window = focused_window.unwrap();
-        match sub {
+
+        // Commands act on the focused window, so the target is focused for as long as it runs.
+        // Unless the command moves the focus anyway, the old focus is restored after it
+        let follow = matches!(action, WindowSubcommand::Focus { .. });
+        let cur_focused = self.monitors.cur().windows.get_focused().map(|win| win.id);
+        let saved_cur = self.monitors.cur_index();
+        let saved_focus = self
+            .monitors
+            .iter()
+            .map(|mon| mon.windows.get_focused().map(|win| win.id))
+            .collect::<Vec<_>>();
+        let restore = cur_focused != Some(id) && !follow;
+        if restore {
+            self.monitors.focus(&MonitorTarget::Index(mon_index));
+            self.monitors.cur_mut().windows.set_focused(id);
+        } else if cur_focused != Some(id) {
+            self.focus(id)?;
+        }
+
+        let new_focused = self.run_window_cmd(&action);
+
+        if restore {
+            for (mon, saved) in self.monitors.iter_mut().zip(saved_focus) {
+                if let Some(saved) = saved.filter(|saved| mon.windows.contains(*saved)) {
+                    mon.windows.set_focused(saved);
+                }
+            }
+            self.monitors.focus(&MonitorTarget::Index(saved_cur));
+            new_focused?;
+        } else if let Some(id) = new_focused? {
+            self.focus(id)?;
+        }
+
+        self.update_windows().context(format!(
+            "Failed to update windows after `Window({:?})`",
+            action
+        ))
+    }
+
+    /// Run the command on the focused window, returning the window that should get focus after it
+    fn run_window_cmd(&mut self, sub: &WindowSubcommand) -> Result<Option<Window>> {
+        let focused_window = match self.monitors.cur().windows.get_focused() {
+            Some(win) => win,
+            None => return Ok(None),
+        };
+        let new_focused = match *sub {
             WindowSubcommand::Destroy => {
                 self.conn
                     .destroy_window(focused_window.id)
                     .context("Failed to destroy the current window")?;
-                return self.on_window_cmd(WindowSubcommand::Focus(Direction::Down));
+                return self.run_window_cmd(&WindowSubcommand::Focus {
+                    direction: FocusDirection::List(Direction::Down),
+                });
             }
             WindowSubcommand::Send(Destination::Tag { tag_id }) => {
                 // We want a mutable window state so we get it again as mut and we know it exists
@@ -68,21 +138,16 @@ impl<'a> WmState<'a> {
                 focused_window.tags.clear();
                 focused_window.tags.insert(tag);
 
-                if let Some(new_focused) = self.monitors.cur().get_next_win() {
-                    let id = new_focused.id;
-                    self.focus(id)?;
-                }
+                self.monitors.cur().get_next_win().map(|win| win.id)
             }
-            WindowSubcommand::Send(Destination::Monitor(dir)) => {
-                if self.monitors.len() > 1 {
+            WindowSubcommand::Send(Destination::Monitor { ref target }) => {
+                let index = self
+                    .monitors
+                    .position(target)
+                    .with_context(|| format!("There is no monitor `{}`", target))?;
+                if index != self.monitors.cur_index() {
+                    let focused_win = focused_window.id;
                     let cur = self.monitors.cur_mut();
-                    let focused_win = cur.windows.get_focused();
-                    if focused_win.is_none() {
-                        // We just need to shift focus
-                        self.handle_command(Command::Monitor(MonitorSubcommand::Focus(dir)))?;
-                        return Ok(());
-                    }
-                    let focused_win = focused_win.unwrap().id;
 
                     let (win, new) = cur.forget(focused_win);
                     if let Some(new) = new {
@@ -92,41 +157,314 @@ impl<'a> WmState<'a> {
                     let win = win.expect("It certainly exists");
                     let id = win.id;
 
-                    self.monitors.focus(dir);
+                    self.monitors.focus(target);
                     self.monitors.cur_mut().windows.push_front(win);
                     self.monitors.cur_mut().windows.set_focused(id);
 
-                    self.focus(id)?;
+                    Some(id)
+                } else {
+                    None
                 }
             }
-            WindowSubcommand::Focus(dir) => {
+            WindowSubcommand::Focus {
+                direction: FocusDirection::List(dir),
+            } => {
                 let new_focused = match dir {
                     Direction::Up => self.monitors.cur().get_prev_win(),
                     Direction::Down => self.monitors.cur().get_next_win(),
                 };
 
-                if let Some(new_focused) = new_focused {
-                    let id = new_focused.id;
-                    self.focus(id)?;
+                new_focused.map(|win| win.id)
+            }
+            WindowSubcommand::Focus {
+                direction: FocusDirection::Spatial(dir),
+            } => {
+                let rect = focused_window.rect();
+                let cur = self.monitors.cur();
+                match cur.windows.find_closest(&rect, dir, &cur.tags) {
+                    Some((_, win)) => Some(win.id),
+                    None => {
+                        // Nothing in that direction here, so look in the next monitor
+                        let target = MonitorTarget::Spatial(dir);
+                        let mon = match self.monitors.position(&target) {
+                            Some(i) => self.monitors.get(i).unwrap(),
+                            None => return Ok(None),
+                        };
+                        match mon.windows.find_closest(&rect, dir, &mon.tags) {
+                            Some((_, win)) => Some(win.id),
+                            None => {
+                                // An empty monitor, we can only focus the monitor itself
+                                return self
+                                    .handle_command(Command::Monitor(MonitorSubcommand::Focus {
+                                        target,
+                                    }))
+                                    .map(|_| None);
+                            }
+                        }
+                    }
                 }
             }
             WindowSubcommand::Shift(dir) => {
                 self.monitors.cur_mut().shift_windows(dir);
-                return self.on_window_cmd(WindowSubcommand::Focus(dir));
+                return self.run_window_cmd(&WindowSubcommand::Focus {
+                    direction: FocusDirection::List(dir),
+                });
+            }
+            WindowSubcommand::Swap { direction } => {
+                let rect = focused_window.rect();
+                let id = focused_window.id;
+                let cur = self.monitors.cur_mut();
+                if let Some((index, _)) = cur.windows.find_closest(&rect, direction, &cur.tags) {
+                    cur.swap_focused(index);
+                    None
+                } else {
+                    // Nothing in that direction here, so try to swap with a window in the next monitor
+                    let target = MonitorTarget::Spatial(direction);
+                    let mon_index = match self.monitors.position(&target) {
+                        Some(i) => i,
+                        None => return Ok(None),
+                    };
+                    let mon = self.monitors.get(mon_index).unwrap();
+                    match mon.windows.find_closest(&rect, direction, &mon.tags) {
+                        Some((win_index, _)) => {
+                            let cur_index = self.monitors.cur_index();
+                            let cur_win_index =
+                                self.monitors.cur().windows.find_by_id(id).unwrap().0;
+                            self.monitors
+                                .swap_windows((cur_index, cur_win_index), (mon_index, win_index));
+                            Some(id)
+                        }
+                        None => {
+                            // The monitor is empty, so just move the window there
+                            return self.run_window_cmd(&WindowSubcommand::Send(
+                                Destination::Monitor { target },
+                            ));
+                        }
+                    }
+                }
             }
+            WindowSubcommand::Zoom => self.monitors.cur_mut().zoom(),
             WindowSubcommand::Toggle(option) => match option {
                 WindowToggle::Float => {
                     if let Some(focused_window) = self.monitors.cur_mut().windows.get_focused_mut()
                     {
                         focused_window.floating = !focused_window.floating;
                     }
+                    None
                 }
             },
         };
 
-        self.update_windows().context(format!(
-            "Failed to update windows after `Window({:?})`",
-            sub
-        ))
+        Ok(new_focused)
+    }
+
+    /// Find the monitor and the window the selector points to, or the focused window if it's empty.
+    /// Windows on the focused monitor come first and visible windows are preferred.
+    /// When cycling, the search starts after the focused window and ends with it
+    fn select_window(
+        &self,
+        selector: &WindowSelector,
+        cycle: Option<Direction>,
+    ) -> Result<Option<(usize, Window)>> {
+        if selector.is_empty() {
+            let cur = self.monitors.cur_index();
+            return Ok(self
+                .monitors
+                .cur()
+                .windows
+                .get_focused()
+                .map(|win| (cur, win.id)));
+        }
+
+        let title = match &selector.title {
+            Some(title) => Some(
+                Regex::new(title).with_context(|| format!("Invalid title regex `{}`", title))?,
+            ),
+            None => None,
+        };
+
+        let cur = self.monitors.cur_index();
+        let mut order = (0..self.monitors.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| i != cur);
+        let windows = order
+            .into_iter()
+            .flat_map(|mon_index| {
+                let mon = self.monitors.get(mon_index).unwrap();
+                mon.windows.iter().map(move |win| (mon_index, mon, win))
+            })
+            .collect::<Vec<_>>();
+        let indices = match cycle {
+            Some(dir) => {
+                let focused = self.monitors.cur().windows.get_focused().map(|win| win.id);
+                let current = windows
+                    .iter()
+                    .position(|(_, _, win)| Some(win.id) == focused);
+                utils::cycle_order(windows.len(), current, dir)
+            }
+            None => (0..windows.len()).collect(),
+        };
+
+        let mut found = None;
+        for i in indices {
+            let (mon_index, mon, win) = windows[i];
+            if !self.selector_matches(selector, title.as_ref(), mon, win) {
+                continue;
+            }
+            if utils::is_visible(win, &mon.tags) {
+                return Ok(Some((mon_index, win.id)));
+            }
+            found = found.or(Some((mon_index, win.id)));
+        }
+
+        Ok(found)
+    }
+
+    /// If the window on the given monitor matches the selector.
+    /// Windows that can't be asked about (ex they are going away) don't match
+    fn selector_matches(
+        &self,
+        selector: &WindowSelector,
+        title: Option<&Regex>,
+        mon: &Monitor,
+        win: &WinState,
+    ) -> bool {
+        let master = mon
+            .windows
+            .iter()
+            .find(|win| win.is_tiled() && utils::is_visible(win, &mon.tags))
+            .map(|win| win.id);
+        if selector.id.is_some_and(|id| id != win.id)
+            || selector.master && master != Some(win.id)
+            || selector.last && self.last_focused != Some(win.id)
+        {
+            return false;
+        }
+        if let Some(class) = &selector.class {
+            let matches = utils::get_wm_class(self.conn, win.id)
+                .is_ok_and(|names| names.iter().any(|name| name.eq_ignore_ascii_case(class)));
+            if !matches {
+                return false;
+            }
+        }
+        if let Some(title) = title {
+            let matches = utils::get_wm_name(self.conn, &self.atoms, win.id)
+                .is_ok_and(|name| title.is_match(&name));
+            if !matches {
+                return false;
+            }
+        }
+        !selector.urgent || utils::is_urgent(self.conn, win.id).unwrap_or(false)
+    }
+
+    pub(crate) fn on_query_cmd(&self, sub: QuerySubcommand) -> Result<Response> {
+        let data = match sub {
+            QuerySubcommand::Windows => {
+                let windows = self
+                    .monitors
+                    .iter()
+                    .flat_map(|mon| mon.windows.iter().map(move |win| (mon, win)))
+                    .collect::<Vec<_>>();
+                let ids = windows.iter().map(|(_, win)| win.id).collect::<Vec<_>>();
+                let props = utils::get_classes_and_titles(self.conn, &self.atoms, &ids)?;
+                // Windows can go away while being asked about, they are left out
+                windows
+                    .into_iter()
+                    .zip(props)
+                    .filter_map(|((mon, win), props)| {
+                        let (class, title) = props?;
+                        Some(Self::window_info(mon, win, &class, &title))
+                    })
+                    .collect()
+            }
+            QuerySubcommand::Monitors => self
+                .monitors
+                .iter()
+                .enumerate()
+                .map(|(index, mon)| {
+                    json!({
+                        "id": mon.id,
+                        "index": index,
+                        "name": mon.name,
+                        "x": mon.rect.x,
+                        "y": mon.rect.y,
+                        "width": mon.rect.width,
+                        "height": mon.rect.height,
+                        "layout": mon.layout,
+                        "focused": index == self.monitors.cur_index(),
+                        "focused_window": mon.windows.get_focused().map(|win| win.id),
+                    })
+                })
+                .collect(),
+            QuerySubcommand::Tags => self
+                .monitors
+                .iter()
+                .flat_map(|mon| {
+                    mon.tags.iter().map(move |tag| {
+                        json!({
+                            "monitor": mon.name,
+                            "id": tag.id,
+                            "visible": tag.visible,
+                            "layout": tag.layout,
+                            "windows": mon.windows.iter().filter(|win| win.tags.contains(&tag.id)).count(),
+                        })
+                    })
+                })
+                .collect(),
+            QuerySubcommand::Layouts => json!({
+                "available": self.config.layouts,
+                "monitors": self
+                    .monitors
+                    .iter()
+                    .map(|mon| json!({ "monitor": mon.name, "layout": mon.layout }))
+                    .collect::<Vec<_>>(),
+            }),
+            QuerySubcommand::Focused => {
+                let mon = self.monitors.cur();
+                let window = match mon.windows.get_focused() {
+                    Some(win) => {
+                        let class = utils::get_wm_class(self.conn, win.id).with_context(|| {
+                            format!("Failed to get the class of window {}", win.id)
+                        })?;
+                        let title = utils::get_wm_name(self.conn, &self.atoms, win.id)
+                            .with_context(|| {
+                                format!("Failed to get the title of window {}", win.id)
+                            })?;
+                        Self::window_info(mon, win, &class, &title)
+                    }
+                    None => Value::Null,
+                };
+                json!({ "monitor": mon.name, "window": window })
+            }
+        };
+
+        Ok(Response::Data(data))
+    }
+
+    /// Describe a window for `Query` commands
+    pub(crate) fn window_info(
+        mon: &Monitor,
+        win: &WinState,
+        class: &[String],
+        title: &str,
+    ) -> Value {
+        let mut tags = win.tags.iter().collect::<Vec<_>>();
+        tags.sort();
+
+        json!({
+            "id": win.id,
+            "monitor": mon.name,
+            "x": win.x,
+            "y": win.y,
+            "width": win.width,
+            "height": win.height,
+            "tags": tags,
+            "floating": win.floating,
+            "fullscreen": win.fullscreen,
+            "visible": utils::is_visible(win, mon.tags.as_slice()),
+            "focused": mon.windows.get_focused().map(|focused| focused.id) == Some(win.id),
+            "instance": class.first(),
+            "class": class.get(1).or_else(|| class.first()),
+            "title": title,
+        })
     }
 }
@@ -1,6 +1,9 @@
 use x11rb::{connection::Connection, errors::ReplyOrIdError, protocol::xproto::*};
 
-use crate::{utils::clean_mask, utils::get_transient_for, WmState};
+use crate::{
+    utils::{self, clean_mask, get_transient_for},
+    WmState,
+};
 
 impl<'a> WmState<'a> {
     pub(crate) fn on_button_press(
@@ -27,7 +30,7 @@ impl<'a> WmState<'a> {
 
         // We handle changing `self.cur_monitor` in `motion_notify` so we can assume that the mouse
         // is in the currently focused monitor
-        if let Some((_, mut window)) = self.monitors.cur_mut().windows.find_by_id_mut(event.event) {
+        if let Some((_, window)) = self.monitors.cur_mut().windows.find_by_id_mut(event.event) {
             window.floating = true;
             if event.detail == 1 && self.resizing_window.is_none() {
                 // Left click -> Move windows
@@ -210,8 +213,39 @@ impl<'a> WmState<'a> {
                 let win_state = self.iter_windows_mut().nth(index).unwrap();
                 win_state.floating = true;
             }
+        } else if event.atom == Atom::from(AtomEnum::WM_NAME)
+            || event.atom == self.atoms._NET_WM_NAME
+        {
+            // The window may be gone already, that's no reason to stop
+            let id = win_state.id;
+            if let Err(e) = self.on_title_change(id) {
+                log::warn!("Failed to apply the title rules to {}: {:#}", id, e);
+            }
         }
 
         Ok(())
     }
+
+    /// Windows ask to enter or leave fullscreen through _NET_WM_STATE, like mpv does on `f`
+    pub(crate) fn on_client_message(&mut self, event: ClientMessageEvent) -> anyhow::Result<()> {
+        if event.type_ != self.atoms._NET_WM_STATE || event.format != 32 {
+            log::trace!("Ignoring {:?}", event);
+            return Ok(());
+        }
+        log::info!("Handling {:?}", event);
+        // The action and up to two states to apply it to
+        let [action, first, second, ..] = event.data.as_data32();
+        if ![first, second].contains(&self.atoms._NET_WM_STATE_FULLSCREEN) {
+            return Ok(());
+        }
+        let fullscreen = match self.iter_windows().find(|win| win.id == event.window) {
+            Some(win) => win.fullscreen,
+            None => return Ok(()),
+        };
+        if let Some(fullscreen) = utils::requested_state(action, fullscreen) {
+            self.set_fullscreen(event.window, fullscreen)?;
+            self.update_windows()?;
+        }
+        Ok(())
+    }
 }
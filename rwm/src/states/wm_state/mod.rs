@@ -1,7 +1,7 @@
 mod command_handlers;
 mod event_handlers;
 
-use std::{collections::HashSet, os::unix::net::UnixStream};
+use std::{path::PathBuf, process::Child};
 
 use {
     anyhow::Context,
@@ -9,23 +9,32 @@ use {
         connection::Connection,
         cursor::Handle as CursorHandle,
         errors::ReplyOrIdError,
-        protocol::{xproto::*, Event},
+        protocol::{randr, xproto::*, Event},
         rust_connection::RustConnection,
+        wrapper::ConnectionExt as _,
     },
 };
 
 use crate::{
+    atoms::Atoms,
+    color::Color,
     config::Config,
     layouts::LayoutType,
     monitors_history::MonitorsHistory,
+    rect::Rect,
+    spawn_rule::{RuleActions, SpawnRule},
     states::{Monitor, WinState},
+    subscriptions::Snapshot,
     utils,
 };
-use common::{Command, ConfigSubcommand, MonitorSubcommand};
+use common::{
+    Command, ConfigSubcommand, MonitorSubcommand, MonitorTarget, Response, TagId, WmEvent,
+};
 
 #[derive(Debug)]
 pub(crate) struct WmState<'a> {
     pub(crate) conn: &'a RustConnection,
+    pub(crate) atoms: Atoms,
     pub(crate) config: Config,
     screen_num: usize,
     pub(crate) running: bool,
@@ -39,6 +48,20 @@ pub(crate) struct WmState<'a> {
     pub(crate) resizing_window: Option<(Window, (i16, i16))>,
 
     pub(crate) cursor_handle: CursorHandle,
+
+    /// The window that had focus before the current one
+    pub(crate) last_focused: Option<Window>,
+    /// While running a batch of commands `update_windows` is put off until the end.
+    /// This is `Some(true)` if windows need to be updated when the batch ends
+    batch_update: Option<bool>,
+    /// The path of the socket clients connect to, removed when the wm exits
+    socket_path: PathBuf,
+    /// The state as the subscribers last saw it
+    snapshot: Snapshot,
+    /// Events for subscribers that can't be found by comparing snapshots
+    events: Vec<WmEvent>,
+    /// Spawned processes that haven't been reaped yet, along with their command
+    children: Vec<(String, Child)>,
 }
 
 impl<'a> WmState<'a> {
@@ -47,21 +70,57 @@ impl<'a> WmState<'a> {
         screen_num: usize,
         config: Config,
         cursor_handle: CursorHandle,
+        socket_path: PathBuf,
     ) -> anyhow::Result<Self> {
         let monitors = if cfg!(feature = "fake_monitors") {
             vec![
-                Monitor::new(&config, crate::rect::Rect::new(0, 0, 960, 1080)),
-                Monitor::new(&config, crate::rect::Rect::new(960, 0, 960, 1080)),
+                Monitor::new(
+                    &config,
+                    String::from("fake-0"),
+                    crate::rect::Rect::new(0, 0, 960, 1080),
+                ),
+                Monitor::new(
+                    &config,
+                    String::from("fake-1"),
+                    crate::rect::Rect::new(960, 0, 960, 1080),
+                ),
             ]
         } else {
+            // Get notified when monitors get (un)plugged or reconfigured
+            let root = conn.setup().roots[screen_num].root;
+            randr::select_input(
+                conn,
+                root,
+                randr::NotifyMask::SCREEN_CHANGE
+                    | randr::NotifyMask::CRTC_CHANGE
+                    | randr::NotifyMask::OUTPUT_CHANGE,
+            )
+            .context("Failed to select randr input")?;
+
             utils::get_monitors(conn, &config, screen_num)?
         };
 
         log::debug!("Initialising with monitors: {:#?}", monitors);
         log::debug!("Initialising with current monitor: {:#?}", monitors[0]);
 
+        let atoms = Atoms::new(conn)
+            .context("Failed to intern atoms")?
+            .reply()
+            .context("Failed to intern atoms")?;
+        // Clients only ask to leave fullscreen and such if they know we'd listen
+        let root = conn.setup().roots[screen_num].root;
+        conn.change_property32(
+            PropMode::REPLACE,
+            root,
+            atoms._NET_SUPPORTED,
+            AtomEnum::ATOM,
+            &[atoms._NET_WM_STATE, atoms._NET_WM_STATE_FULLSCREEN],
+        )
+        .context("Failed to set _NET_SUPPORTED")?;
+
         Ok(Self {
             conn,
+            atoms,
             config,
             screen_num,
             running: true,
@@ -69,6 +128,12 @@ impl<'a> WmState<'a> {
             dragging_window: None,
             resizing_window: None,
             cursor_handle,
+            last_focused: None,
+            batch_update: None,
+            socket_path,
+            snapshot: Snapshot::default(),
+            events: Vec::new(),
+            children: Vec::new(),
         })
     }
 
@@ -80,71 +145,178 @@ impl<'a> WmState<'a> {
         self.monitors.iter_mut().flat_map(|m| m.windows.iter_mut())
     }
 
-    /// Apply user defined rules on the given window (ex put it in tag 2 by default)
-    fn apply_rules(&mut self, window: &mut WinState) -> Result<(), ReplyOrIdError> {
-        if self.config.class_rules.is_empty() && self.config.name_rules.is_empty() {
-            // No rules to apply
+    /// Ask RandR for the current monitors and rebuild `self.monitors`.
+    /// Called when monitors get (un)plugged or change geometry
+    pub(crate) fn update_monitors(&mut self) -> anyhow::Result<()> {
+        if cfg!(feature = "fake_monitors") {
             return Ok(());
         }
 
-        let class_names = self
-            .conn
-            .get_property(
-                false,
-                window.id,
-                AtomEnum::WM_CLASS,
-                AtomEnum::STRING,
-                0,
-                1024,
-            )?
-            .reply()?
-            .value; // Alternative we would do .value8().unwrap().collect::<Vec<_>>();
-
-        // We should usually get two values, technically the first is the instance name and the second one the class name
-        // Technically we should check against the second value only but instead we will check against both
-        // I believe some apps only return one value (could be wrong on that) but also checking both is more user friendly
-        // If there is some reason to *not* do this please raise an issue!
-        let class_names = String::from_utf8(class_names)
-            .expect("utf 8")
-            .trim_matches('\0')
-            .split('\0')
-            .map(|s| s.to_string())
-            .collect::<Vec<_>>();
-
-        for name in class_names.iter() {
-            if let Some(tag_ids) = self.config.class_rules.get(name) {
-                let mut tags = HashSet::new();
-                for tag_id in tag_ids.iter() {
-                    tags.insert(tag_id.to_owned());
-                }
-                window.tags = tags;
+        let monitors = utils::get_monitors(self.conn, &self.config, self.screen_num)?;
+        if monitors.is_empty() {
+            // This can happen for a moment while outputs are being reconfigured
+            log::warn!("RandR reported no monitors, ignoring the change");
+            return Ok(());
+        }
+        self.monitors.replace(monitors);
+        log::debug!("Monitors changed to: {:#?}", self.monitors);
 
-                return Ok(());
+        // Floating windows don't get moved by the layouts, so make sure they are not left
+        // outside of their monitor
+        for mon in self.monitors.iter_mut() {
+            let (x, y) = (mon.rect.x, mon.rect.y);
+            let outside = mon
+                .windows
+                .iter_mut()
+                .filter(|win| win.floating && !mon.rect.contains_point(win.x, win.y));
+            for win in outside {
+                win.x = x;
+                win.y = y;
+                self.conn
+                    .configure_window(win.id, &ConfigureWindowAux::new().x(x as i32).y(y as i32))?;
             }
         }
 
-        let wm_name = self
-            .conn
-            .get_property(
-                false,
-                window.id,
-                AtomEnum::WM_NAME,
-                AtomEnum::STRING,
-                0,
-                1024,
-            )?
-            .reply()?
-            .value;
-        let wm_name = String::from_utf8(wm_name).expect("utf 8");
-
-        if let Some(tag_ids) = self.config.class_rules.get(&wm_name) {
-            let mut tags = HashSet::new();
-            for tag_id in tag_ids.iter() {
-                tags.insert(tag_id.to_owned());
-            }
-            window.tags = tags;
+        if let Some(win) = self.monitors.cur().windows.get_focused() {
+            let id = win.id;
+            self.focus(id)?;
+        }
+
+        self.update_windows()
+            .context("Failed to update windows after monitors changed")
+    }
+
+    /// The indices of the user defined rules that match the given window
+    fn matching_rules(&self, window: Window) -> anyhow::Result<Vec<usize>> {
+        if self.config.rules.is_empty() {
+            return Ok(Vec::new());
         }
 
+        let props = utils::get_window_props(self.conn, &self.atoms, window)?;
+        log::debug!("Matching rules against {:?}", props);
+        Ok(self
+            .config
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches(&props))
+            .map(|(i, _)| i)
+            .collect())
+    }
+
+    /// What the given rules do to a window. Every one of them applies, in order
+    fn rule_actions(&self, rules: &[usize]) -> RuleActions {
+        let mut actions = RuleActions::default();
+        for &i in rules {
+            self.config.rules[i].apply(&mut actions);
+        }
+        actions
+    }
+
+    /// Only the rules that look at the title, out of the given ones
+    fn title_rules(&self, rules: &[usize]) -> Vec<usize> {
+        rules
+            .iter()
+            .copied()
+            .filter(|&i| self.config.rules[i].uses_title())
+            .collect()
+    }
+
+    /// Make the window cover its whole monitor or bring it back to where it was
+    pub(crate) fn set_fullscreen(
+        &mut self,
+        id: Window,
+        fullscreen: bool,
+    ) -> Result<(), ReplyOrIdError> {
+        let win = match self.iter_windows_mut().find(|win| win.id == id) {
+            Some(win) => win,
+            None => return Ok(()),
+        };
+        win.fullscreen = fullscreen;
+        let rect = win.rect();
+        if !fullscreen {
+            // Tiled windows get placed by the layout, floating ones go back
+            let config = ConfigureWindowAux::new()
+                .x(rect.x as i32)
+                .y(rect.y as i32)
+                .width(rect.width as u32)
+                .height(rect.height as u32)
+                .border_width(self.config.border_width);
+            self.conn.configure_window(id, &config)?;
+        }
+        Self::set_net_wm_state(self.conn, &self.atoms, id, fullscreen)
+    }
+
+    /// Let the window know if it's fullscreen, it's the only state we keep track of
+    fn set_net_wm_state(
+        conn: &RustConnection,
+        atoms: &Atoms,
+        id: Window,
+        fullscreen: bool,
+    ) -> Result<(), ReplyOrIdError> {
+        let states: &[Atom] = if fullscreen {
+            &[atoms._NET_WM_STATE_FULLSCREEN]
+        } else {
+            &[]
+        };
+        conn.change_property32(
+            PropMode::REPLACE,
+            id,
+            atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            states,
+        )?;
+        Ok(())
+    }
+
+    /// Apply what the rules say to a window on the monitor with the given rect (ex put it in tag 2)
+    fn apply_rules(
+        conn: &RustConnection,
+        atoms: &Atoms,
+        window: &mut WinState,
+        actions: &RuleActions,
+        rect: &Rect,
+    ) -> Result<(), ReplyOrIdError> {
+        if let Some(tags) = &actions.tags {
+            window.tags = tags.clone();
+        }
+        if actions.sticky {
+            window.tags = (1..=9_u8).map(TagId::from_int_unchecked).collect();
+        }
+        if let Some(floating) = actions.floating {
+            window.floating = floating;
+        }
+        if actions.fullscreen {
+            window.fullscreen = true;
+            Self::set_net_wm_state(conn, atoms, window.id, true)?;
+        }
+
+        let mut moved = false;
+        if let Some(geometry) = actions.geometry {
+            window.x = rect.x.saturating_add(geometry.x);
+            window.y = rect.y.saturating_add(geometry.y);
+            window.width = geometry.width;
+            window.height = geometry.height;
+            window.floating = true;
+            moved = true;
+        }
+        if actions.centre {
+            let centre = |start: i16, outer: u16, inner: u16| {
+                (start as i32 + (outer as i32 - inner as i32) / 2).max(start as i32) as i16
+            };
+            window.x = centre(rect.x, rect.width, window.width);
+            window.y = centre(rect.y, rect.height, window.height);
+            window.floating = true;
+            moved = true;
+        }
+        if moved {
+            let config = ConfigureWindowAux::new()
+                .x(window.x as i32)
+                .y(window.y as i32)
+                .width(window.width as u32)
+                .height(window.height as u32);
+            conn.configure_window(window.id, &config)?;
+        }
         Ok(())
     }
 
@@ -180,7 +352,8 @@ impl<'a> WmState<'a> {
         self.update_windows()
     }
 
-    fn manage_window(&mut self, window: Window) -> Result<(), ReplyOrIdError> {
+    /// Start managing a new window, returning if it should get focus
+    fn manage_window(&mut self, window: Window) -> Result<bool, ReplyOrIdError> {
         // Add a border
         let config = ConfigureWindowAux::default().border_width(self.config.border_width);
         self.conn.configure_window(window, &config)?;
@@ -199,12 +372,21 @@ impl<'a> WmState<'a> {
         // Get Button Press events
         utils::grab_buttons(self.conn, window, self.config.mod_key, false)?;
 
+        let rules = self.matching_rules(window).unwrap_or_else(|e| {
+            log::warn!("Failed to apply the rules to {}: {:#}", window, e);
+            Vec::new()
+        });
+        let actions = self.rule_actions(&rules);
+        let cur = self.monitors.cur_index();
+        let mon_index = self.rule_monitor(&actions, window).unwrap_or(cur);
+
         let mut geom = self.conn.get_geometry(window)?.reply()?;
-        let cur_monitor = self.monitors.cur();
-        if cur_monitor.layout == LayoutType::Floating {
+        // The index comes from `position` or it's the current one, so it exists
+        let mon = self.monitors.get(mon_index).unwrap();
+        if mon.layout == LayoutType::Floating {
             // Since it won't be tilled into the correct monitor, we need to make sure it is where it should be
-            geom.x = cur_monitor.rect.x;
-            geom.y = cur_monitor.rect.y;
+            geom.x = mon.rect.x;
+            geom.y = mon.rect.y;
 
             let config = ConfigureWindowAux::new()
                 .x(geom.x as i32)
@@ -220,8 +402,7 @@ impl<'a> WmState<'a> {
         self.conn.map_window(window)?;
 
         // We give a reference to the tags so the window can deduce what tags are currently visible.
-        // We also push at the front of the focus history because the window now has focus
-        let mut window = WinState::new(window, &geom, cur_monitor.tags.as_slice());
+        let mut window = WinState::new(window, &geom, mon.tags.as_slice());
 
         // If it's a transient window then copy parent's tags and make it floating
         // ToDo: Put it in the same monitor as parent as well
@@ -233,9 +414,105 @@ impl<'a> WmState<'a> {
         }
 
         // Apply the user defined rules about where the window should spawn
-        self.apply_rules(&mut window)?;
-        self.monitors.cur_mut().windows.push_front(window);
-        Ok(())
+        window.title_rules = self.title_rules(&rules);
+        Self::apply_rules(self.conn, &self.atoms, &mut window, &actions, &mon.rect)?;
+
+        let visible = utils::is_visible(&window, &mon.tags);
+        let id = window.id;
+        let mon = self.monitors.get_mut(mon_index).unwrap();
+        mon.windows.push_front(window);
+        if mon_index == cur {
+            Ok(visible && !actions.no_focus)
+        } else {
+            // Focus stays on the current monitor, but the window is there when going to its own
+            if visible && !actions.no_focus {
+                mon.windows.set_focused(id);
+            }
+            Ok(false)
+        }
+    }
+
+    /// The index of the monitor the rules put a window on, if they say so and it exists
+    fn rule_monitor(&self, actions: &RuleActions, window: Window) -> Option<usize> {
+        // The config is validated, so the target parses
+        let target = actions.monitor.as_ref()?.parse::<MonitorTarget>().unwrap();
+        let index = self.monitors.position(&target);
+        if index.is_none() {
+            log::warn!("There is no monitor `{}` for window {}", target, window);
+        }
+        index
+    }
+
+    /// Apply the title rules that match a window now but didn't before.
+    /// Lots of programs (ex browsers) only set their title after the window is mapped
+    pub(crate) fn on_title_change(&mut self, window: Window) -> anyhow::Result<()> {
+        // Titles change all the time, so don't ask the X server for anything unless it matters
+        if !self.config.rules.iter().any(SpawnRule::uses_title) {
+            return Ok(());
+        }
+        let title_rules = match self.matching_rules(window) {
+            Ok(rules) => self.title_rules(&rules),
+            Err(e) => {
+                log::warn!("Failed to apply the rules to {}: {:#}", window, e);
+                return Ok(());
+            }
+        };
+        let mon_index = match self
+            .monitors
+            .iter()
+            .position(|mon| mon.contains_window(window))
+        {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let mon = self.monitors.get_mut(mon_index).unwrap();
+        let (_, win) = mon.windows.find_by_id_mut(window).unwrap();
+        let new_rules = title_rules
+            .iter()
+            .copied()
+            .filter(|i| !win.title_rules.contains(i))
+            .collect::<Vec<_>>();
+        win.title_rules = title_rules;
+        if new_rules.is_empty() {
+            return Ok(());
+        }
+
+        log::info!(
+            "Applying rules {:?} to {} after its title changed",
+            new_rules,
+            window
+        );
+        let actions = self.rule_actions(&new_rules);
+        let target = self.rule_monitor(&actions, window).unwrap_or(mon_index);
+        let rect = self.monitors.get(target).unwrap().rect;
+        let mon = self.monitors.get_mut(mon_index).unwrap();
+        let (_, win) = mon.windows.find_by_id_mut(window).unwrap();
+        Self::apply_rules(self.conn, &self.atoms, win, &actions, &rect)?;
+
+        if target != mon_index {
+            let (win, new) = mon.forget(window);
+            let new = new.map(|win| win.id);
+            let win = win.expect("It certainly exists");
+            self.monitors
+                .get_mut(target)
+                .unwrap()
+                .windows
+                .push_front(win);
+            if let Some(new) = new {
+                if mon_index == self.monitors.cur_index() {
+                    self.focus(new)?;
+                } else {
+                    self.monitors
+                        .get_mut(mon_index)
+                        .unwrap()
+                        .windows
+                        .set_focused(new);
+                }
+            }
+        }
+
+        self.fix_hidden_focus()?;
+        Ok(self.update_windows()?)
     }
 
     /// Called when a window gets destroyed (DestroyNotify)
@@ -269,8 +546,9 @@ impl<'a> WmState<'a> {
         match event {
             Event::MapRequest(event) => {
                 log::info!("Handling {:?}", event);
-                self.manage_window(event.window)?;
-                self.focus(event.window)?;
+                if self.manage_window(event.window)? {
+                    self.focus(event.window)?;
+                }
                 self.update_windows()?;
             }
             Event::ButtonPress(event) => self.on_button_press(event)?,
@@ -279,6 +557,11 @@ impl<'a> WmState<'a> {
             Event::DestroyNotify(event) => self.unmanage_window(event.window)?,
             Event::EnterNotify(event) => self.on_enter_notify(event)?,
             Event::PropertyNotify(event) => self.on_property_notify(event)?,
+            Event::ClientMessage(event) => self.on_client_message(event)?,
+            Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_) => {
+                log::info!("Handling {:?}", event);
+                self.update_monitors()?
+            }
             _ => {
                 log::trace!("Ignoring event {:?}", event)
             }
@@ -286,24 +569,219 @@ impl<'a> WmState<'a> {
         Ok(())
     }
 
-    /// Handle a client from the socket
-    pub(crate) fn handle_client(&mut self, stream: &mut UnixStream) -> anyhow::Result<String> {
-        let cmd = common::read_message(stream)?;
-        log::trace!("Got command from socket: {}", cmd);
-        let cmd: Command = serde_json::from_str(&cmd).context("Invalid command")?;
+    /// The events that happened since the last call, for the subscribed clients
+    pub(crate) fn take_events(&mut self) -> Vec<WmEvent> {
+        let snapshot = Snapshot::new(&self.monitors);
+        let mut events = std::mem::take(&mut self.events);
+        events.extend(self.snapshot.diff(&snapshot));
+        self.snapshot = snapshot;
+        events
+    }
+
+    /// Load the given config file, or the current one again. If that fails the old config is kept.
+    /// Either way subscribers get to know about it
+    pub(crate) fn load_config(&mut self, path: Option<PathBuf>) -> anyhow::Result<()> {
+        let result = self.config.load(path).and_then(|_| {
+            self.apply_config()
+                .context("Failed to apply the configuration to the windows")?;
+            self.update_windows()
+                .context("Failed to update windows after loading configuration")
+        });
+        match &result {
+            Ok(_) => self.spawn_all(&self.config.on_reload.clone()),
+            Err(e) => log::error!("Failed to load the config: {:#}", e),
+        }
+
+        self.events.push(WmEvent::Config {
+            path: self
+                .config
+                .path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+        result
+    }
+
+    /// Run a shell command, keeping the child around until it's reaped
+    pub(crate) fn spawn(&mut self, cmd: &str) -> anyhow::Result<()> {
+        let child = utils::spawn(cmd, &self.socket_path)
+            .with_context(|| format!("Failed to run `{}`", cmd))?;
+        log::info!("Started `{}` with pid {}", cmd, child.id());
+        self.children.push((cmd.to_string(), child));
+        Ok(())
+    }
+
+    /// Run every command, logging the ones that fail to start
+    pub(crate) fn spawn_all(&mut self, cmds: &[String]) {
+        for cmd in cmds {
+            if let Err(e) = self.spawn(cmd) {
+                log::error!("{:#}", e);
+            }
+        }
+    }
+
+    /// Wait on the children that exited so they don't stay around as zombies
+    pub(crate) fn reap_children(&mut self) {
+        self.children
+            .retain_mut(|(cmd, child)| match child.try_wait() {
+                Ok(None) => true,
+                Ok(Some(status)) => {
+                    if status.success() {
+                        log::debug!("`{}` exited", cmd);
+                    } else {
+                        log::warn!("`{}` exited with {}", cmd, status);
+                    }
+                    false
+                }
+                Err(e) => {
+                    log::error!("Failed to wait on `{}`: {}", cmd, e);
+                    false
+                }
+            });
+    }
+
+    /// Bring the monitors and every managed window in line with a newly loaded config
+    fn apply_config(&mut self) -> Result<(), ReplyOrIdError> {
+        // Layouts that are not in the config anymore can't be cycled through
+        let default_layout = self.config.layouts[0];
+        for mon in self.monitors.iter_mut() {
+            if !self.config.layouts.contains(&mon.layout) {
+                mon.layout = default_layout;
+            }
+            for tag in mon.tags.iter_mut() {
+                if !self.config.layouts.contains(&tag.layout) {
+                    tag.layout = default_layout;
+                }
+            }
+        }
+
+        // Only the tags are applied again, the rest is about how windows show up.
+        // The title rules are remembered again since their indices may have changed
+        let windows = self.iter_windows().map(|win| win.id).collect::<Vec<_>>();
+        for &id in windows.iter() {
+            match self.matching_rules(id) {
+                Ok(rules) => {
+                    let actions = self.rule_actions(&rules);
+                    let title_rules = self.title_rules(&rules);
+                    let tags = if actions.sticky {
+                        Some((1..=9_u8).map(TagId::from_int_unchecked).collect())
+                    } else {
+                        actions.tags
+                    };
+                    if let Some(win) = self.iter_windows_mut().find(|win| win.id == id) {
+                        if let Some(tags) = tags {
+                            win.tags = tags;
+                        }
+                        win.title_rules = title_rules;
+                    }
+                }
+                Err(e) => log::warn!("Failed to apply the rules to {}: {:#}", id, e),
+            }
+        }
+
+        self.reapply_appearance()?;
+        self.fix_hidden_focus()
+    }
+
+    /// Apply the borders and the mod key of the config to every window.
+    /// The rules aren't run again, so tags changed by hand stay
+    fn reapply_appearance(&self) -> Result<(), ReplyOrIdError> {
+        let focused = self.monitors.cur().windows.get_focused().map(|win| win.id);
+        for win in self.iter_windows() {
+            let id = win.id;
+            let is_focused = focused == Some(id);
+            let border_color = if is_focused {
+                self.config.focused_border_color
+            } else {
+                self.config.normal_border_color
+            };
+            if !win.fullscreen {
+                self.conn.configure_window(
+                    id,
+                    &ConfigureWindowAux::new().border_width(self.config.border_width),
+                )?;
+            }
+            self.conn.change_window_attributes(
+                id,
+                &ChangeWindowAttributesAux::new().border_pixel(border_color.pixel(win.depth)),
+            )?;
+            utils::grab_buttons(self.conn, id, self.config.mod_key, is_focused)?;
+        }
+        Ok(())
+    }
+
+    /// The rules may have moved focused windows to tags that are not visible,
+    /// so focus something that can be seen instead
+    fn fix_hidden_focus(&mut self) -> Result<(), ReplyOrIdError> {
+        let cur = self.monitors.cur_index();
+        for (index, mon) in self.monitors.iter_mut().enumerate() {
+            let hidden = mon
+                .windows
+                .get_focused()
+                .is_some_and(|win| !utils::is_visible(win, &mon.tags));
+            if hidden && index != cur {
+                mon.reset_focus();
+            }
+        }
+        if let Some(win) = self.monitors.cur().windows.get_focused() {
+            if !utils::is_visible(win, &self.monitors.cur().tags) {
+                match self.monitors.cur().get_next_win().map(|win| win.id) {
+                    Some(id) => self.focus(id)?,
+                    None => {
+                        self.monitors.cur_mut().reset_focus();
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the commands in order, updating the windows only once at the end
+    pub(crate) fn handle_batch(&mut self, cmds: Vec<Command>) -> Response {
+        log::info!("Handling batch of {} commands", cmds.len());
+        self.batch_update = Some(false);
+        let mut responses = Self::run_batch(cmds, |cmd| self.handle_command(cmd));
+
+        if self.batch_update.take() == Some(true) {
+            if let Err(e) = self.update_windows() {
+                log::error!("Failed to update windows after batch: {}", e);
+                responses.push(Response::Error(format!(
+                    "Failed to update windows after the batch: {}",
+                    e
+                )));
+            }
+        }
+
+        Response::Batch(responses)
+    }
 
-        self.handle_command(cmd)
+    /// Run every command with `run`, even after one fails, and collect what each one returned
+    pub(crate) fn run_batch(
+        cmds: Vec<Command>,
+        mut run: impl FnMut(Command) -> anyhow::Result<Response>,
+    ) -> Vec<Response> {
+        cmds.into_iter()
+            .map(|cmd| match cmd {
+                Command::Subscribe { .. } => {
+                    Response::Error(String::from("Can't subscribe as part of a batch"))
+                }
+                cmd => Response::from_result(run(cmd)),
+            })
+            .collect()
     }
 
     /// Handle the command from a client
-    fn handle_command(&mut self, cmd: Command) -> anyhow::Result<String> {
+    pub(crate) fn handle_command(&mut self, cmd: Command) -> anyhow::Result<Response> {
         log::info!("Handling command {:?}", cmd);
         match cmd {
             Command::Quit => {
                 self.running = false;
             }
+            Command::Spawn { cmd } => self.spawn(&cmd)?,
             Command::Tag(sub) => self.on_tag_cmd(sub)?,
-            Command::Window(sub) => self.on_window_cmd(sub)?,
+            Command::Window(cmd) => self.on_window_cmd(cmd)?,
             Command::Layout(sub) => {
                 self.monitors
                     .cur_mut()
@@ -312,40 +790,67 @@ impl<'a> WmState<'a> {
                     format!("Failed to update windows after `Layout({:?})`", sub)
                 })?
             }
+            Command::Query(sub) => return self.on_query_cmd(sub),
+            Command::Subscribe { events } => {
+                // The client is kept as a subscriber by `Ipc` once the reply is sent
+                log::info!("New subscriber for {:?}", events);
+            }
             Command::Config(ConfigSubcommand::Print) => {
-                return ron::ser::to_string_pretty(&self.config, ron::ser::PrettyConfig::default())
-                    .context("Failed to serialise current configuration");
+                let config =
+                    ron::ser::to_string_pretty(&self.config, ron::ser::PrettyConfig::default())
+                        .context("Failed to serialise current configuration")?;
+                return Ok(Response::Data(config.into()));
+            }
+            Command::Config(ConfigSubcommand::Load { path }) => self.load_config(path)?,
+            Command::Config(ConfigSubcommand::Check { path }) => {
+                Config::parse(&path)?;
             }
-            Command::Config(ConfigSubcommand::Load { path }) => {
-                self.config.load(path)?;
+            Command::Config(ConfigSubcommand::Get { key }) => {
+                return Ok(Response::Data(self.config.get(key).into()))
+            }
+            Command::Config(ConfigSubcommand::Set { key, value }) => {
+                self.config.set(key, &value)?;
+                self.reapply_appearance()
+                    .context("Failed to apply the configuration to the windows")?;
                 self.update_windows()
-                    .context("Failed to update windows after loading configuration")?;
+                    .with_context(|| format!("Failed to update windows after setting `{}`", key))?
             }
-            Command::Monitor(MonitorSubcommand::Focus(dir)) => {
-                if self.monitors.len() > 1 {
+            Command::Config(ConfigSubcommand::Save) => self.config.save()?,
+            Command::Monitor(MonitorSubcommand::Focus { target }) => {
+                let index = self
+                    .monitors
+                    .position(&target)
+                    .with_context(|| format!("There is no monitor `{}`", target))?;
+                if index != self.monitors.cur_index() {
                     if let Some(win) = self.monitors.cur().windows.get_focused() {
                         let id = win.id;
                         self.unfocus(id)?;
                     }
-                    self.monitors.focus(dir);
+                    self.monitors.focus(&target);
                     if let Some(win) = self.monitors.cur().windows.get_focused() {
                         let id = win.id;
                         self.focus(id)?;
                     }
-                    self.update_windows().context(format!(
-                        "Failed to update windows after `Monitor(MonitorSubcommand::Focus({:?}))`",
-                        dir
-                    ))?;
+                    self.update_windows().with_context(|| {
+                        format!(
+                            "Failed to update windows after `Monitor(MonitorSubcommand::Focus({:?}))`",
+                            target
+                        )
+                    })?;
                 }
             }
         }
 
-        Ok(String::from("0"))
+        Ok(Response::Success)
     }
 
     /// Update the currently visible windows
     pub(crate) fn update_windows(&mut self) -> Result<(), ReplyOrIdError> {
         // Should this be replaced entirely by layout.update()?
+        if let Some(pending) = self.batch_update.as_mut() {
+            *pending = true;
+            return Ok(());
+        }
 
         for mon in self.monitors.iter() {
             // Map the proper windows and unmap the rest
@@ -387,9 +892,19 @@ impl<'a> WmState<'a> {
         Ok(())
     }
 
+    /// The pixel of the color for the border of the given window, which depends on its depth
+    fn border_pixel(&self, id: Window, color: Color) -> u32 {
+        // Windows we don't know about are most likely the usual 24-bit ones
+        let depth = self
+            .iter_windows()
+            .find(|win| win.id == id)
+            .map_or(24, |win| win.depth);
+        color.pixel(depth)
+    }
+
     pub(crate) fn unfocus(&self, id: Window) -> Result<(), ReplyOrIdError> {
-        let attrs =
-            ChangeWindowAttributesAux::default().border_pixel(self.config.normal_border_color);
+        let attrs = ChangeWindowAttributesAux::default()
+            .border_pixel(self.border_pixel(id, self.config.normal_border_color));
         self.conn.change_window_attributes(id, &attrs)?;
 
         Ok(())
@@ -402,6 +917,9 @@ impl<'a> WmState<'a> {
             if self.monitors.len() == 1 && old_focused.id == id {
                 return Ok(());
             }
+            if old_focused.id != id {
+                self.last_focused = Some(old_focused.id);
+            }
             utils::grab_buttons(self.conn, old_focused.id, self.config.mod_key, false)?;
 
             self.unfocus(old_focused.id)?;
@@ -415,8 +933,8 @@ impl<'a> WmState<'a> {
         self.monitors.cur_mut().windows.set_focused(id);
 
         // Give it the correct border color
-        let attrs =
-            ChangeWindowAttributesAux::default().border_pixel(self.config.focused_border_color);
+        let attrs = ChangeWindowAttributesAux::default()
+            .border_pixel(self.border_pixel(id, self.config.focused_border_color));
         self.conn.change_window_attributes(id, &attrs)?;
         // Give keyboard input to window
         self.conn
@@ -429,6 +947,6 @@ impl<'a> WmState<'a> {
 impl<'a> Drop for WmState<'a> {
     fn drop(&mut self) {
         // This is done here instead of the `utils::clean_up` so that it will run on a panic too
-        let _ = std::fs::remove_file("/tmp/rwm.sock");
+        let _ = std::fs::remove_file(&self.socket_path);
     }
 }
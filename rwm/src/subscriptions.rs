@@ -0,0 +1,98 @@
+//! The bookkeeping needed to find out what changed for clients that subscribed to events
+
+use std::collections::BTreeSet;
+
+use x11rb::protocol::xproto::Window;
+
+use crate::monitors_history::MonitorsHistory;
+use common::{TagId, WmEvent};
+
+/// The parts of the state that subscribers get events about
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Snapshot {
+    monitors: Vec<String>,
+    focused_monitor: String,
+    focused_window: Option<Window>,
+    /// The visible tags of every monitor
+    tags: Vec<(String, Vec<TagId>)>,
+    /// The layout of every monitor
+    layouts: Vec<(String, String)>,
+    windows: BTreeSet<Window>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(monitors: &MonitorsHistory) -> Self {
+        Self {
+            monitors: monitors.iter().map(|mon| mon.name.clone()).collect(),
+            focused_monitor: monitors.cur().name.clone(),
+            focused_window: monitors.cur().windows.get_focused().map(|win| win.id),
+            tags: monitors
+                .iter()
+                .map(|mon| {
+                    let visible = mon
+                        .tags
+                        .iter()
+                        .filter(|tag| tag.visible)
+                        .map(|tag| tag.id)
+                        .collect();
+                    (mon.name.clone(), visible)
+                })
+                .collect(),
+            layouts: monitors
+                .iter()
+                .map(|mon| (mon.name.clone(), format!("{:?}", mon.layout)))
+                .collect(),
+            windows: monitors
+                .iter()
+                .flat_map(|mon| mon.windows.iter().map(|win| win.id))
+                .collect(),
+        }
+    }
+
+    /// The events that describe going from `self` to `new`
+    pub(crate) fn diff(&self, new: &Self) -> Vec<WmEvent> {
+        let mut events = Vec::new();
+
+        if self.monitors != new.monitors || self.focused_monitor != new.focused_monitor {
+            events.push(WmEvent::Monitor {
+                focused: new.focused_monitor.clone(),
+                monitors: new.monitors.clone(),
+            });
+        }
+
+        for &window in new.windows.difference(&self.windows) {
+            events.push(WmEvent::WindowManaged { window });
+        }
+        for &window in self.windows.difference(&new.windows) {
+            events.push(WmEvent::WindowUnmanaged { window });
+        }
+
+        for (monitor, visible) in new.tags.iter() {
+            if !self.tags.contains(&(monitor.clone(), visible.clone())) {
+                events.push(WmEvent::Tag {
+                    monitor: monitor.clone(),
+                    visible: visible.clone(),
+                });
+            }
+        }
+
+        for (monitor, layout) in new.layouts.iter() {
+            if !self.layouts.contains(&(monitor.clone(), layout.clone())) {
+                events.push(WmEvent::Layout {
+                    monitor: monitor.clone(),
+                    layout: layout.clone(),
+                });
+            }
+        }
+
+        if self.focused_monitor != new.focused_monitor || self.focused_window != new.focused_window
+        {
+            events.push(WmEvent::Focus {
+                monitor: new.focused_monitor.clone(),
+                window: new.focused_window,
+            });
+        }
+
+        events
+    }
+}
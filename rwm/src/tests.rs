@@ -1,6 +1,31 @@
-use std::path::PathBuf;
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use crate::utils::expand_tilde;
+use x11rb::protocol::xproto::GetGeometryReply;
+
+use common::{
+    Command, ConfigKey, Direction, ProtocolError, Request, Response, SpatialDirection, TagId,
+    WmEvent,
+};
+
+use crate::{
+    color::Color,
+    config::{config_paths, Config},
+    config_watcher::ConfigWatcher,
+    ipc::{Client, Ipc, MAX_CLIENTS},
+    monitors_history::MonitorsHistory,
+    rect::Rect,
+    spawn_rule::{RuleActions, WindowProps},
+    states::{Monitor, WinState, WmState},
+    subscriptions::Snapshot,
+    utils::{
+        bind_socket, child_exits, cycle_order, decode_text, drain, expand_tilde, requested_state,
+        spawn,
+    },
+};
 
 #[test]
 fn test_tilde_expand() {
@@ -23,3 +48,950 @@ fn test_tilde_expand() {
     let p = expand_tilde(&PathBuf::from("/~/blah"));
     assert_eq!(p, PathBuf::from("/~/blah"));
 }
+
+#[test]
+fn test_monitors_replace() {
+    let config = Config::default();
+    let mon = |name: &str, x| Monitor::new(&config, name.to_string(), Rect::new(x, 0, 100, 100));
+
+    let mut monitors = MonitorsHistory::new(vec![mon("DP-1", 0), mon("HDMI-0", 100)]);
+    let dp_id = monitors.iter().next().unwrap().id;
+
+    // HDMI-0 gets unplugged and DP-1 moves
+    monitors.replace(vec![mon("DP-1", 50)]);
+    assert_eq!(monitors.len(), 1);
+    assert_eq!(monitors.cur().id, dp_id);
+    assert_eq!(monitors.cur().rect.x, 50);
+
+    // A new monitor gets plugged in
+    monitors.replace(vec![mon("DP-1", 0), mon("DP-2", 100)]);
+    assert_eq!(monitors.len(), 2);
+    assert_eq!(monitors.cur().id, dp_id);
+    assert_eq!(monitors.iter().nth(1).unwrap().name, "DP-2");
+}
+
+#[test]
+fn test_rect_closest() {
+    // A 2x2 grid with a wide rect under it
+    let rects = [
+        Rect::new(0, 0, 100, 100),
+        Rect::new(100, 0, 100, 100),
+        Rect::new(0, 100, 100, 100),
+        Rect::new(100, 100, 100, 100),
+        Rect::new(0, 200, 200, 100),
+    ];
+
+    assert_eq!(rects[0].closest(SpatialDirection::Right, &rects), Some(1));
+    assert_eq!(rects[0].closest(SpatialDirection::Down, &rects), Some(2));
+    assert_eq!(rects[0].closest(SpatialDirection::Left, &rects), None);
+    assert_eq!(rects[0].closest(SpatialDirection::Up, &rects), None);
+    assert_eq!(rects[3].closest(SpatialDirection::Left, &rects), Some(2));
+    assert_eq!(rects[3].closest(SpatialDirection::Down, &rects), Some(4));
+    assert_eq!(rects[4].closest(SpatialDirection::Up, &rects), Some(2));
+}
+
+#[test]
+fn test_zoom() {
+    let config = Config::default();
+    let mut mon = Monitor::new(&config, String::from("DP-1"), Rect::new(0, 0, 100, 100));
+    let geom = GetGeometryReply {
+        depth: 0,
+        sequence: 0,
+        length: 0,
+        root: 0,
+        x: 0,
+        y: 0,
+        width: 10,
+        height: 10,
+        border_width: 0,
+    };
+    for id in (1..=3).rev() {
+        mon.windows
+            .push_front(WinState::new(id, &geom, mon.tags.as_slice()));
+    }
+    let ids = |mon: &Monitor| mon.windows.iter().map(|win| win.id).collect::<Vec<_>>();
+
+    // Zooming a window that's not the master makes it the master
+    mon.windows.set_focused(3);
+    assert_eq!(mon.zoom(), Some(3));
+    assert_eq!(ids(&mon), vec![3, 2, 1]);
+    assert_eq!(mon.windows.get_focused().unwrap().id, 3);
+
+    // Zooming the master swaps it with the next window, which should get focus
+    assert_eq!(mon.zoom(), Some(2));
+    assert_eq!(ids(&mon), vec![2, 3, 1]);
+}
+
+#[test]
+fn test_swap_focused() {
+    let config = Config::default();
+    let mut mon = Monitor::new(&config, String::from("DP-1"), Rect::new(0, 0, 100, 100));
+    let geom = GetGeometryReply {
+        depth: 0,
+        sequence: 0,
+        length: 0,
+        root: 0,
+        x: 0,
+        y: 0,
+        width: 10,
+        height: 10,
+        border_width: 0,
+    };
+    for id in (1..=3).rev() {
+        mon.windows
+            .push_front(WinState::new(id, &geom, mon.tags.as_slice()));
+    }
+    let ids = |mon: &Monitor| mon.windows.iter().map(|win| win.id).collect::<Vec<_>>();
+    mon.windows.set_focused(1);
+
+    // A window on a tag that's not visible stays where it is
+    let (_, hidden) = mon.windows.find_by_id_mut(3).unwrap();
+    hidden.tags = [TagId::from_int_unchecked(9)].into_iter().collect();
+    assert!(!mon.swap_focused(2));
+    assert_eq!(ids(&mon), vec![1, 2, 3]);
+
+    // Nor does swapping a window with itself do anything
+    assert!(!mon.swap_focused(0));
+    assert!(!mon.swap_focused(5));
+    assert_eq!(ids(&mon), vec![1, 2, 3]);
+
+    assert!(mon.swap_focused(1));
+    assert_eq!(ids(&mon), vec![2, 1, 3]);
+    assert_eq!(mon.windows.get_focused().unwrap().id, 1);
+}
+
+#[test]
+fn test_leave_fullscreen() {
+    // _NET_WM_STATE removes, adds or toggles the state
+    assert_eq!(requested_state(0, true), Some(false));
+    assert_eq!(requested_state(2, true), Some(false));
+    assert_eq!(requested_state(2, false), Some(true));
+    assert_eq!(requested_state(1, false), Some(true));
+    assert_eq!(requested_state(3, true), None);
+
+    // A window a rule made fullscreen goes back to the layout where it was
+    let config = Config::default();
+    let mon = Monitor::new(&config, String::from("DP-1"), Rect::new(0, 0, 100, 100));
+    let geom = GetGeometryReply {
+        depth: 0,
+        sequence: 0,
+        length: 0,
+        root: 0,
+        x: 5,
+        y: 5,
+        width: 10,
+        height: 10,
+        border_width: 0,
+    };
+    let mut win = WinState::new(1, &geom, mon.tags.as_slice());
+    win.fullscreen = true;
+    assert!(!win.is_tiled());
+    win.fullscreen = requested_state(0, win.fullscreen).unwrap();
+    assert!(win.is_tiled());
+    assert_eq!((win.x, win.y, win.width, win.height), (5, 5, 10, 10));
+}
+
+#[test]
+fn test_snapshot_diff() {
+    let config = Config::default();
+    let mut monitors = MonitorsHistory::new(vec![Monitor::new(
+        &config,
+        String::from("DP-1"),
+        Rect::new(0, 0, 100, 100),
+    )]);
+    let old = Snapshot::new(&monitors);
+    assert!(old.diff(&Snapshot::new(&monitors)).is_empty());
+
+    let tag = TagId::from_int_unchecked(3);
+    monitors.cur_mut().switch_tag(tag);
+    let events = old.diff(&Snapshot::new(&monitors));
+    assert_eq!(
+        events,
+        vec![WmEvent::Tag {
+            monitor: String::from("DP-1"),
+            visible: vec![tag],
+        }]
+    );
+}
+
+#[test]
+fn test_config_watcher() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-watcher-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.ron");
+    fs::write(&config, "()").unwrap();
+
+    let mut watcher = ConfigWatcher::new().unwrap();
+    watcher.watch(std::slice::from_ref(&config));
+    assert!(!watcher.changed());
+
+    fs::write(&config, "(gap: 2)").unwrap();
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+
+    // Other files in the same directory don't matter
+    fs::write(dir.join("other.ron"), "()").unwrap();
+    assert!(!watcher.changed());
+
+    // Editors often write a new file and move it over the old one
+    let new = dir.join(".config.ron.swp");
+    fs::write(&new, "(gap: 3)").unwrap();
+    fs::rename(&new, &config).unwrap();
+    assert!(watcher.changed());
+
+    // Included files are watched too, wherever they are
+    fs::create_dir_all(dir.join("team")).unwrap();
+    fs::write(dir.join("team/base.ron"), "()").unwrap();
+    fs::write(&config, r#"(include: ["team/base.ron", "other.ron"])"#).unwrap();
+    let loaded = Config::from_file(config.clone()).unwrap();
+    assert_eq!(loaded.files.len(), 3);
+    watcher.watch(&loaded.files);
+    watcher.changed();
+    fs::write(dir.join("team/base.ron"), "(gap: 5)").unwrap();
+    assert!(watcher.changed());
+    fs::write(dir.join("other.ron"), "(gap: 6)").unwrap();
+    assert!(watcher.changed());
+
+    watcher.watch(&[]);
+    fs::write(&config, "(gap: 4)").unwrap();
+    assert!(!watcher.changed());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_config_save_is_not_reloaded() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-saved-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.ron");
+    fs::write(&path, r#"(on_reload: ["notify-send reloaded"])"#).unwrap();
+
+    let mut config = Config::from_file(path.clone()).unwrap();
+    let mut watcher = ConfigWatcher::new().unwrap();
+    watcher.watch(&config.files);
+    assert!(!config.is_saved());
+
+    // The watcher sees the write, but the main loop leaves the config and `on_reload` alone
+    config.set(ConfigKey::Gap, "7").unwrap();
+    config.save().unwrap();
+    assert!(watcher.changed());
+    assert!(config.is_saved());
+
+    // Editing it afterwards loads it again
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    fs::write(&path, "(gap: 3)").unwrap();
+    assert!(watcher.changed());
+    assert!(!config.is_saved());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_config_load_validates() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-validate-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.ron");
+
+    let mut bad = Config::default();
+    bad.layouts.clear();
+    fs::write(&path, ron::ser::to_string(&bad).unwrap()).unwrap();
+
+    let mut config = Config::default();
+    assert!(config.load(Some(path.clone())).is_err());
+    // The old config is kept when the new one is invalid
+    assert!(!config.layouts.is_empty());
+    assert!(config.path.is_none());
+    assert!(Config::from_file(path).is_err());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_config_paths() {
+    let home = Path::new("/home/user/.config");
+    assert_eq!(
+        config_paths(Some(home), None),
+        vec![
+            PathBuf::from("/home/user/.config/rwm/config.ron"),
+            PathBuf::from("/etc/xdg/rwm/config.ron"),
+        ]
+    );
+    assert_eq!(
+        config_paths(Some(home), Some(OsStr::new("/opt/xdg::/etc/xdg"))),
+        vec![
+            PathBuf::from("/home/user/.config/rwm/config.ron"),
+            PathBuf::from("/opt/xdg/rwm/config.ron"),
+            PathBuf::from("/etc/xdg/rwm/config.ron"),
+        ]
+    );
+    assert_eq!(
+        config_paths(None, Some(OsStr::new(""))),
+        vec![PathBuf::from("/etc/xdg/rwm/config.ron")]
+    );
+}
+
+#[test]
+fn test_config_check() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-check-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.ron");
+    let check = |content: &str| {
+        fs::write(&path, content).unwrap();
+        Config::parse(&path).map_err(|e| format!("{:#}", e))
+    };
+
+    let default = ron::ser::to_string_pretty(&Config::default(), Default::default()).unwrap();
+    assert!(check(&default).is_ok());
+
+    // Errors from serde have no position of their own
+    let err = check(&default.replacen("    gap", "    gapp", 1)).unwrap_err();
+    assert!(err.starts_with(&format!("{}:", path.display())));
+    assert!(err.contains("unknown field `gapp`"));
+    let line = default.lines().position(|l| l.contains("gap")).unwrap() + 1;
+    assert!(err.contains(&format!(":{}:", line)), "{}", err);
+
+    let err = check(&default.replacen("border_width: 4", "border_width: \"4\"", 1)).unwrap_err();
+    assert!(err.contains(":2:19:"), "{}", err);
+
+    let mut bad = Config::default();
+    bad.layouts.clear();
+    let bad = ron::ser::to_string(&bad).unwrap().replace(
+        "rules:[]",
+        r#"rules:[ClassName("a",[(1)]),ClassName("a",[(10)]),WmName("a",[(0)])]"#,
+    );
+    let err = check(&bad).unwrap_err();
+    assert_eq!(err.lines().count(), 3, "{}", err);
+    assert!(err.contains("at least one layout"));
+    assert!(err.contains("uses tag 10"));
+    assert!(err.contains("uses tag 0"));
+
+    let err = check(&default.replace(
+        "rules: []",
+        r#"rules: [Rule(class: "a", geometry: (x: 0, y: 0, width: 0, height: 10))]"#,
+    ))
+    .unwrap_err();
+    assert!(
+        err.starts_with(&format!(
+            "{}: The rule number 1 gives windows a size of 0x10",
+            path.display()
+        )),
+        "{}",
+        err
+    );
+
+    // Duplicates are checked once the rest is fine
+    let err = check(&default.replace(
+        "rules: []",
+        r#"rules: [ClassName("a", [(1)]), ClassName("a", [(2)])]"#,
+    ))
+    .unwrap_err();
+    assert!(
+        err.contains("more than one rule for the class `a`"),
+        "{}",
+        err
+    );
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_config_include() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-include-{}", std::process::id()));
+    fs::create_dir_all(dir.join("team")).unwrap();
+    fs::write(
+        dir.join("team/base.ron"),
+        r#"(gap: 8, border_width: 2, layouts: [Grid], rules: [ClassName("firefox", [(2)])])"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("team/colors.ron"),
+        r##"(gap: 6, focused_border_color: "#FF0000")"##,
+    )
+    .unwrap();
+    let config = dir.join("config.ron");
+    fs::write(
+        &config,
+        r#"(
+            include: ["team/base.ron", "team/colors.ron"],
+            gap: 2,
+            rules: [ClassName("Alacritty", [(3)])],
+        )"#,
+    )
+    .unwrap();
+
+    let parsed = Config::parse(&config).unwrap();
+    // The file overrides its includes, which override each other in order
+    assert_eq!(parsed.gap, 2);
+    assert_eq!(parsed.border_width, 2);
+    assert_eq!(
+        parsed.focused_border_color,
+        "#FF0000".parse::<Color>().unwrap()
+    );
+    // Missing fields come from the default config
+    assert_eq!(parsed.follow_cursor, Config::default().follow_cursor);
+    assert_eq!(parsed.layouts.len(), 1);
+
+    let loaded = Config::from_file(config.clone()).unwrap();
+    assert_eq!(loaded.rules.len(), 2);
+
+    // A file can have the same rule as the files it includes, the later one wins
+    let content = fs::read_to_string(&config).unwrap();
+    fs::write(&config, content.replace("Alacritty", "firefox")).unwrap();
+    let overridden = Config::parse(&config).unwrap();
+    let firefox = WindowProps {
+        class: String::from("firefox"),
+        ..Default::default()
+    };
+    let mut actions = RuleActions::default();
+    for rule in overridden
+        .rules
+        .iter()
+        .filter(|rule| rule.matches(&firefox))
+    {
+        rule.apply(&mut actions);
+    }
+    assert_eq!(
+        actions.tags,
+        Some([TagId::from_int_unchecked(3)].into_iter().collect())
+    );
+    fs::write(&config, content).unwrap();
+
+    // Errors point at the included file
+    fs::write(dir.join("team/colors.ron"), "(gapp: 6)").unwrap();
+    let err = format!("{:#}", Config::parse(&config).unwrap_err());
+    assert!(err.contains("colors.ron:1:"), "{}", err);
+
+    fs::write(
+        dir.join("team/colors.ron"),
+        r#"(include: ["../config.ron"])"#,
+    )
+    .unwrap();
+    let err = format!("{:#}", Config::parse(&config).unwrap_err());
+    assert!(err.contains("including itself"), "{}", err);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_config_set() {
+    let mut config = Config::default();
+    config.set(ConfigKey::Gap, "12").unwrap();
+    assert_eq!(config.get(ConfigKey::Gap), "12");
+    config.set(ConfigKey::FollowCursor, "false").unwrap();
+    assert!(!config.follow_cursor);
+    config
+        .set(ConfigKey::FocusedBorderColor, "#ff8800")
+        .unwrap();
+    assert_eq!(config.get(ConfigKey::FocusedBorderColor), "#FF8800");
+    config.set(ConfigKey::ModKey, "mod4").unwrap();
+    assert_eq!(config.get(ConfigKey::ModKey), "Mod 4");
+
+    // Bad values leave the config as it was
+    let err = config.set(ConfigKey::BorderWidth, "-1").unwrap_err();
+    assert!(format!("{:#}", err).contains("`border_width`"));
+    assert_eq!(config.border_width, Config::default().border_width);
+    assert!(config
+        .set(ConfigKey::NormalBorderColor, "blue-ish")
+        .is_err());
+    assert!(config.set(ConfigKey::ModKey, "hyper").is_err());
+
+    // Saving needs a file to save to
+    assert!(config.save().is_err());
+    let dir = std::env::temp_dir().join(format!("rwm-test-save-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    config.path = Some(dir.join("config.ron"));
+    config.save().unwrap();
+    let saved = Config::parse(&dir.join("config.ron")).unwrap();
+    assert_eq!(saved.gap, 12);
+    assert_eq!(saved.focused_border_color, config.focused_border_color);
+
+    // Saving would merge the included files into the one including them
+    fs::write(dir.join("base.ron"), "(gap: 2)").unwrap();
+    fs::write(dir.join("config.ron"), r#"(include: ["base.ron"])"#).unwrap();
+    let mut included = Config::from_file(dir.join("config.ron")).unwrap();
+    assert!(included.save().is_err());
+    assert_eq!(
+        fs::read_to_string(dir.join("config.ron")).unwrap(),
+        r#"(include: ["base.ron"])"#
+    );
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_spawn() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-spawn-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out");
+
+    let cmd = format!(
+        "echo \"$RWM_SOCKET $$ $(cut -d' ' -f6 /proc/$$/stat)\" > '{}'",
+        out.display()
+    );
+    let mut child = spawn(&cmd, Path::new("/tmp/rwm-test.sock")).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let out = fs::read_to_string(out).unwrap();
+    let fields = out.split_whitespace().collect::<Vec<_>>();
+    assert_eq!(fields[0], "/tmp/rwm-test.sock");
+    // The child leads its own session
+    assert_eq!(fields[1], fields[2]);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_child_exits() {
+    let mut exits = child_exits().unwrap();
+    let poller = polling::Poller::new().unwrap();
+    poller.add(&exits, polling::Event::readable(1)).unwrap();
+
+    let mut child = spawn("true", Path::new("/tmp/rwm-test.sock")).unwrap();
+    // Other tests spawn children too, so wait until it's ours that exited
+    let mut events = Vec::new();
+    loop {
+        events.clear();
+        // The signal itself can interrupt the wait, then the stream is ready the next time
+        let woken = match poller.wait(&mut events, Some(std::time::Duration::from_secs(5))) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            woken => woken.unwrap(),
+        };
+        assert!(woken > 0, "No wake up for the exited child");
+        drain(&mut exits);
+        if child.try_wait().unwrap().is_some() {
+            break;
+        }
+        poller.modify(&exits, polling::Event::readable(1)).unwrap();
+    }
+}
+
+#[test]
+fn test_spawn_rules() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-rules-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.ron");
+    fs::write(
+        &path,
+        r#"(rules: [
+            ClassName("firefox", [(2)]),
+            Rule(class: "mpv", floating: true, sticky: true),
+            Rule(title: "^Picture-in-Picture$", geometry: (x: 10, y: 10, width: 480, height: 270)),
+            Rule(window_type: "dialog", centre: true, no_focus: true),
+            Rule(instance: "Navigator", role: "browser", tags: [(3)], monitor: "DP-1"),
+        ])"#,
+    )
+    .unwrap();
+    let config = Config::parse(&path).unwrap();
+
+    let actions = |props: &WindowProps| {
+        let mut actions = RuleActions::default();
+        for rule in config.rules.iter().filter(|rule| rule.matches(props)) {
+            rule.apply(&mut actions);
+        }
+        actions
+    };
+    let props = |instance: &str, class: &str, title: &str, role: &str, kind: &str| WindowProps {
+        instance: instance.to_string(),
+        class: class.to_string(),
+        title: title.to_string(),
+        role: role.to_string(),
+        window_types: vec![kind.to_string()],
+    };
+
+    // Every matching rule applies, later ones overriding the tags of earlier ones
+    let firefox = actions(&props(
+        "Navigator",
+        "firefox",
+        "Mozilla",
+        "browser",
+        "normal",
+    ));
+    assert_eq!(
+        firefox.tags,
+        Some([TagId::from_int_unchecked(3)].into_iter().collect())
+    );
+    assert_eq!(firefox.monitor.as_deref(), Some("DP-1"));
+    assert!(!firefox.no_focus);
+
+    let pip = actions(&props(
+        "Toolkit",
+        "firefox",
+        "Picture-in-Picture",
+        "",
+        "normal",
+    ));
+    assert_eq!(pip.tags.unwrap().len(), 1);
+    assert_eq!(pip.geometry.unwrap().width, 480);
+    assert!(pip.monitor.is_none());
+
+    let mpv = actions(&props("gl", "mpv", "video.mkv", "", "dialog"));
+    assert!(mpv.sticky && mpv.centre && mpv.no_focus);
+    assert_eq!(mpv.floating, Some(true));
+    assert!(mpv.tags.is_none());
+
+    // The printed config can be read back
+    let printed = ron::ser::to_string_pretty(&config, Default::default()).unwrap();
+    fs::write(&path, &printed).unwrap();
+    assert_eq!(Config::parse(&path).unwrap().rules.len(), 5);
+
+    fs::write(
+        &path,
+        r#"(rules: [Rule(tags: [(2)]), Rule(class: "a", window_type: "window")])"#,
+    )
+    .unwrap();
+    let err = format!("{:#}", Config::parse(&path).unwrap_err());
+    assert!(
+        err.contains("rule number 1 has nothing to match"),
+        "{}",
+        err
+    );
+    assert!(err.contains("window type `window`"), "{}", err);
+
+    fs::write(&path, r#"(rules: [Rule(title: "(")])"#).unwrap();
+    assert!(Config::parse(&path).is_err());
+    fs::write(&path, r#"(rules: [Rule(clas: "mpv")])"#).unwrap();
+    let err = format!("{:#}", Config::parse(&path).unwrap_err());
+    assert!(err.contains("unknown field `clas`"), "{}", err);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_title_rules() {
+    let dir = std::env::temp_dir().join(format!("rwm-test-title-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.ron");
+    fs::write(
+        &path,
+        r#"(rules: [
+            WmName("Café", [(4)]),
+            Rule(title: "YouTube", tags: [(5)]),
+            Rule(class: "mpv", floating: true),
+        ])"#,
+    )
+    .unwrap();
+    let config = Config::parse(&path).unwrap();
+
+    let props = |title: &str| WindowProps {
+        title: title.to_string(),
+        ..Default::default()
+    };
+    // WmName rules match the whole title
+    assert!(config.rules[0].matches(&props("Café")));
+    assert!(!config.rules[0].matches(&props("Café - Firefox")));
+    assert!(config.rules[1].matches(&props("Music - YouTube")));
+
+    let uses_title = config
+        .rules
+        .iter()
+        .map(|rule| rule.uses_title())
+        .collect::<Vec<_>>();
+    assert_eq!(uses_title, [true, true, false]);
+
+    // WM_NAME is usually Latin-1 but sometimes UTF-8
+    assert_eq!(decode_text("Café".as_bytes().to_vec()), "Café");
+    assert_eq!(decode_text(vec![b'C', b'a', b'f', 0xe9]), "Café");
+    assert_eq!(decode_text(Vec::new()), "");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_color() {
+    let parse = |s: &str| s.parse::<Color>().unwrap();
+    let orange = Color::new(255, 136, 0);
+    for s in [
+        "#FF8800",
+        "ff8800",
+        "#f80",
+        "#FF8800FF",
+        "rgb(255, 136, 0)",
+        "rgb(255 136 0)",
+        "RGBA(100%, 53.3%, 0%, 1)",
+    ] {
+        assert_eq!(parse(s), orange, "{}", s);
+    }
+
+    let translucent = Color::with_alpha(255, 136, 0, 128);
+    for s in [
+        "#FF880080",
+        "rgba(255, 136, 0, 0.5)",
+        "rgb(255 136 0 / 50%)",
+    ] {
+        let color = parse(s);
+        assert_eq!(String::from(color), "#FF880080", "{}", s);
+    }
+    assert_eq!(parse("#f808"), Color::with_alpha(255, 136, 0, 136));
+    // Compositors expect premultiplied ARGB, windows without alpha get the colour as it is
+    assert_eq!(orange.pixel(32), 0xFFFF8800);
+    assert_eq!(translucent.pixel(32), 0x80804400);
+    assert_eq!(translucent.pixel(24), 0x00FF8800);
+
+    assert_eq!(parse("Dark Slate Gray"), Color::new(47, 79, 79));
+    assert_eq!(parse("rebeccapurple"), Color::new(102, 51, 153));
+    // X11 names too, but CSS wins where they differ
+    assert_eq!(parse("navyblue"), Color::new(0, 0, 128));
+    assert_eq!(parse("Light Goldenrod"), Color::new(238, 221, 130));
+    assert_eq!(parse("grey0"), Color::new(0, 0, 0));
+    assert_eq!(parse("gray50"), Color::new(127, 127, 127));
+    assert_eq!(parse("gray100"), Color::new(255, 255, 255));
+    assert_eq!(parse("red3"), Color::new(205, 0, 0));
+    assert_eq!(parse("gray"), Color::new(128, 128, 128));
+    assert_eq!(String::from(parse("transparent")), "#00000000");
+
+    for s in [
+        "#FF888",
+        "#GG8800",
+        "rgb(256, 0, 0)",
+        "rgb(1, 2)",
+        "hsl(0, 0%, 0%)",
+        "blurple",
+        "gray101",
+    ] {
+        assert!(s.parse::<Color>().is_err(), "{}", s);
+    }
+
+    // Every color is written the way it's read back
+    for color in [orange, translucent, parse("transparent"), Color::blue()] {
+        let printed = ron::to_string(&color).unwrap();
+        assert_eq!(
+            ron::from_str::<Color>(&printed).unwrap(),
+            color,
+            "{}",
+            printed
+        );
+        assert_eq!(color.to_string().parse::<Color>().unwrap(), color);
+    }
+    assert_eq!(ron::to_string(&orange).unwrap(), "\"#FF8800\"");
+}
+
+#[test]
+fn test_window_info() {
+    let config = Config::default();
+    let mut mon = Monitor::new(&config, String::from("DP-1"), Rect::new(0, 0, 100, 100));
+    let geom = GetGeometryReply {
+        depth: 0,
+        sequence: 0,
+        length: 0,
+        root: 0,
+        x: 1,
+        y: 2,
+        width: 30,
+        height: 40,
+        border_width: 0,
+    };
+    mon.windows
+        .push_front(WinState::new(7, &geom, mon.tags.as_slice()));
+    mon.windows.set_focused(7);
+    let win = mon.windows.iter().next().unwrap();
+
+    let class = [String::from("Navigator"), String::from("firefox")];
+    let info = WmState::window_info(&mon, win, &class, "Mozilla");
+    // Clients depend on this JSON, so it has to stay the same
+    assert_eq!(
+        info,
+        serde_json::json!({
+            "id": 7,
+            "monitor": "DP-1",
+            "x": 1,
+            "y": 2,
+            "width": 30,
+            "height": 40,
+            "tags": [1],
+            "floating": false,
+            "fullscreen": false,
+            "visible": true,
+            "focused": true,
+            "instance": "Navigator",
+            "class": "firefox",
+            "title": "Mozilla",
+        })
+    );
+
+    // Windows with only one part in WM_CLASS use it for both
+    let info = WmState::window_info(&mon, win, &class[1..], "");
+    assert_eq!(info["instance"], info["class"]);
+}
+
+#[test]
+fn test_bind_socket() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("rwm-test-socket-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("rwm.sock");
+
+    let listener = bind_socket(&path).unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    // The private directory it was bound in is gone
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // Someone is listening, so the socket is not taken away from them
+    let err = format!("{:#}", bind_socket(&path).unwrap_err());
+    assert!(err.contains("already listening"), "{}", err);
+    assert!(path.exists());
+
+    // A socket nobody listens on is left over from a crash
+    drop(listener);
+    assert!(path.exists());
+    bind_socket(&path).unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_ipc_framing() {
+    use std::{io::Write, os::unix::net::UnixStream};
+
+    let (mut other, ours) = UnixStream::pair().unwrap();
+    ours.set_nonblocking(true).unwrap();
+    let mut client = Client::new(ours);
+    // Nothing was sent yet, so the client only has a while to do it
+    assert!(client.deadline.is_some());
+
+    let mut sent = common::handshake().to_vec();
+    sent.extend(common::into_message(Command::Quit).unwrap());
+    sent.extend(common::into_message(Request::Batch(vec![Command::Quit])).unwrap());
+    // Half of the handshake, then the rest along with a message and a half
+    let (first, rest) = sent.split_at(2);
+    let (second, third) = rest.split_at(rest.len() - 5);
+
+    other.write_all(first).unwrap();
+    assert!(client.receive().unwrap());
+    assert!(client.next_message().is_none());
+
+    other.write_all(second).unwrap();
+    assert!(client.receive().unwrap());
+    let msg = client.next_message().unwrap().unwrap();
+    assert!(matches!(serde_json::from_str(&msg).unwrap(), Command::Quit));
+    assert!(client.next_message().is_none());
+    // Half a message doesn't get to stay around forever
+    client.update_deadline();
+    assert!(client.deadline.is_some());
+
+    other.write_all(third).unwrap();
+    assert!(client.receive().unwrap());
+    let msg = client.next_message().unwrap().unwrap();
+    assert!(matches!(
+        serde_json::from_str(&msg).unwrap(),
+        Request::Batch(_)
+    ));
+    // Idle clients can stay
+    client.deadline = None;
+    client.update_deadline();
+    assert!(client.deadline.is_none());
+
+    // We answered the handshake
+    let mut reply = [0; common::HANDSHAKE_LEN];
+    std::io::Read::read_exact(&mut other, &mut reply).unwrap();
+    assert_eq!(reply, common::handshake());
+
+    drop(other);
+    assert!(!client.receive().unwrap());
+}
+
+#[test]
+fn test_ipc_limits() {
+    use std::{io::Write, os::unix::net::UnixStream, thread};
+
+    let (mut other, ours) = UnixStream::pair().unwrap();
+    ours.set_nonblocking(true).unwrap();
+    let mut client = Client::new(ours);
+
+    // A length that's too big is refused without waiting for the data
+    other.write_all(&common::handshake()).unwrap();
+    other
+        .write_all(&((common::MAX_MESSAGE_SIZE + 1) as u32).to_be_bytes())
+        .unwrap();
+    client.receive().unwrap();
+    assert!(matches!(
+        client.next_message(),
+        Some(Err(ProtocolError::TooBig(_)))
+    ));
+    assert!(client.next_message().is_none());
+
+    // A client that keeps sending is dropped instead of buffered forever
+    let (mut other, ours) = UnixStream::pair().unwrap();
+    ours.set_nonblocking(true).unwrap();
+    let mut client = Client::new(ours);
+    let writer = thread::spawn(move || {
+        let junk = vec![b'a'; 1024];
+        while other.write_all(&junk).is_ok() {}
+    });
+    while !client.flooded() {
+        client.receive().unwrap();
+    }
+    drop(client);
+    writer.join().unwrap();
+
+    // Only so many clients can be connected at once
+    let dir = std::env::temp_dir().join(format!("rwm-test-ipc-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("rwm.sock");
+    let listener = bind_socket(&path).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let poller = polling::Poller::new().unwrap();
+    let mut ipc = Ipc::new(listener);
+    let mut streams = (0..=MAX_CLIENTS)
+        .map(|_| UnixStream::connect(&path).unwrap())
+        .collect::<Vec<_>>();
+    ipc.accept(&poller);
+    assert!(ipc.timeout().unwrap() <= std::time::Duration::from_secs(2));
+    // The last one is closed right away
+    let mut buf = [0; 1];
+    let last = streams.last_mut().unwrap();
+    assert_eq!(std::io::Read::read(last, &mut buf).unwrap(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_run_batch() {
+    let cmds = vec![
+        Command::Spawn {
+            cmd: String::from("first"),
+        },
+        Command::Spawn {
+            cmd: String::from("fails"),
+        },
+        Command::Subscribe { events: Vec::new() },
+        Command::Spawn {
+            cmd: String::from("last"),
+        },
+    ];
+    let mut ran = Vec::new();
+    let responses = WmState::run_batch(cmds, |cmd| match cmd {
+        Command::Spawn { cmd } => {
+            ran.push(cmd.clone());
+            if cmd == "fails" {
+                anyhow::bail!("`{}` failed", cmd);
+            }
+            Ok(Response::Success)
+        }
+        cmd => panic!("Unexpected {:?}", cmd),
+    });
+
+    // A failing command is reported and the rest still run
+    assert_eq!(ran, ["first", "fails", "last"]);
+    assert_eq!(
+        responses,
+        [
+            Response::Success,
+            Response::Error(String::from("`fails` failed")),
+            Response::Error(String::from("Can't subscribe as part of a batch")),
+            Response::Success,
+        ]
+    );
+}
+
+#[test]
+fn test_cycle_order() {
+    // The focused window comes last, so it's still found when it's the only match
+    assert_eq!(cycle_order(4, Some(1), Direction::Down), [2, 3, 0, 1]);
+    assert_eq!(cycle_order(4, Some(1), Direction::Up), [0, 3, 2, 1]);
+    assert_eq!(cycle_order(1, Some(0), Direction::Down), [0]);
+    assert_eq!(cycle_order(3, None, Direction::Down), [0, 1, 2]);
+    assert_eq!(cycle_order(3, None, Direction::Up), [2, 1, 0]);
+    assert!(cycle_order(0, None, Direction::Down).is_empty());
+}
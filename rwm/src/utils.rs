@@ -1,26 +1,43 @@
 use std::{
     env,
+    ffi::OsString,
+    fs::{self, DirBuilder, Permissions},
+    io::{self, Read},
+    mem,
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        io::IntoRawFd,
+        net::{UnixListener, UnixStream},
+        process::CommandExt,
+    },
     path::{Path, PathBuf},
+    process::{self, Child, Stdio},
+    ptr,
+    sync::atomic::{AtomicI32, Ordering},
 };
 
 use {
-    anyhow::{anyhow, Context, Result},
+    anyhow::{anyhow, bail, Context, Result},
     flexi_logger::{FlexiLoggerError, LoggerHandle},
     time::{format_description::FormatItem, macros::format_description},
     x11rb::{
         connection::Connection as _,
         errors::ReplyOrIdError,
+        properties::WmHints,
         protocol::{randr, xproto::*},
         rust_connection::RustConnection,
     },
 };
 
 use crate::{
+    atoms::Atoms,
     config::Config,
     mod_mask::XModMask,
     rect::Rect,
+    spawn_rule::WindowProps,
     states::{Monitor, TagState, WinState},
 };
+use common::Direction;
 
 /// Precompiled version of the time stamp format that is used by the provided format functions.
 const TS_FORMAT: &[FormatItem<'static>] =
@@ -47,6 +64,80 @@ pub(crate) fn clean_up() -> Result<()> {
     Ok(())
 }
 
+/// Run a shell command in its own session, so it outlives the wm and doesn't get its signals.
+/// It gets the wm's environment, `DISPLAY` included, with `RWM_SOCKET` pointing to the wm's socket.
+/// The child has to be waited on to be reaped
+pub(crate) fn spawn(cmd: &str, socket_path: &Path) -> io::Result<Child> {
+    let mut command = process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(cmd)
+        .env("RWM_SOCKET", socket_path)
+        .stdin(Stdio::null());
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    command.spawn()
+}
+
+/// The end of the stream the SIGCHLD handler writes to
+static CHILD_EXITS: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_sigchld(_: libc::c_int) {
+    let fd = CHILD_EXITS.load(Ordering::Relaxed);
+    // SAFETY: write is async-signal-safe and the buffer outlives the call. The stream is
+    // non-blocking, and when it's full there already is a wake up pending. errno is restored
+    // so the code that got interrupted doesn't see the write's errors
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(fd, [0_u8].as_ptr().cast(), 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+/// A stream that becomes readable whenever a child exits, so they can be reaped right away
+pub(crate) fn child_exits() -> io::Result<UnixStream> {
+    let (read, write) = UnixStream::pair()?;
+    read.set_nonblocking(true)?;
+    write.set_nonblocking(true)?;
+    CHILD_EXITS.store(write.into_raw_fd(), Ordering::Relaxed);
+
+    // SAFETY: the handler only does async-signal-safe things. SA_RESTART keeps the signal from
+    // failing the calls it interrupts, other than waiting for events
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_sigchld as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART | libc::SA_NOCLDSTOP;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGCHLD, &action, ptr::null_mut()) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(read)
+}
+
+/// Read everything there is to read from a non-blocking stream
+pub(crate) fn drain(stream: &mut UnixStream) {
+    let mut buffer = [0; 64];
+    while matches!(stream.read(&mut buffer), Ok(n) if n > 0) {}
+}
+
+/// What a _NET_WM_STATE message asks a state to become, given if it's set now
+pub(crate) fn requested_state(action: u32, current: bool) -> Option<bool> {
+    match action {
+        0 => Some(false),
+        1 => Some(true),
+        2 => Some(!current),
+        _ => None,
+    }
+}
+
 /// Get all the visible tags only
 pub(crate) fn visible(tags: &[TagState]) -> Vec<TagState> {
     tags.iter().filter(|tag| tag.visible).copied().collect()
@@ -75,6 +166,245 @@ pub(crate) fn get_transient_for(conn: &RustConnection, win_id: Window) -> Result
         .next())
 }
 
+/// The indices of `len` items in the order `next`/`prev` goes through them starting from `current`,
+/// wrapping around and ending with `current` itself
+pub(crate) fn cycle_order(len: usize, current: Option<usize>, dir: Direction) -> Vec<usize> {
+    match (current, dir) {
+        (None, Direction::Down) => (0..len).collect(),
+        (None, Direction::Up) => (0..len).rev().collect(),
+        (Some(cur), Direction::Down) => (1..=len).map(|i| (cur + i) % len).collect(),
+        (Some(cur), Direction::Up) => (1..=len).map(|i| (cur + len - i) % len).collect(),
+    }
+}
+
+/// Bind to the socket so that only our user can ever connect to it.
+/// A socket left over from a crash is removed, but not one another wm is still listening on
+pub(crate) fn bind_socket(path: &Path) -> Result<UnixListener> {
+    match UnixStream::connect(path) {
+        Ok(_) => bail!(
+            "Another wm is already listening on `{}`, use --socket to pick another path",
+            path.display()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            log::warn!("Removing stale socket {}", path.display());
+            fs::remove_file(path).context("Failed to remove stale socket")?;
+        }
+        Err(_) => {}
+    }
+
+    // Bound in a directory only we can enter and then moved in place, so there is no moment
+    // when other users could connect to it
+    let file_name = path
+        .file_name()
+        .context("The socket path has no file name")?;
+    let mut dir_name = OsString::from(".");
+    dir_name.push(file_name);
+    dir_name.push(format!(".{}", process::id()));
+    let dir = path.with_file_name(dir_name);
+    fs::remove_dir_all(&dir).ok();
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+    let hidden = dir.join(file_name);
+    let listener = UnixListener::bind(&hidden)
+        .and_then(|listener| {
+            // Only our user should be able to control the wm
+            fs::set_permissions(&hidden, Permissions::from_mode(0o600))?;
+            fs::rename(&hidden, path)?;
+            Ok(listener)
+        })
+        .with_context(|| format!("Failed to bind to socket `{}`", path.display()));
+    fs::remove_dir_all(&dir).ok();
+    listener
+}
+
+/// Get the WM_CLASS of the given window.
+/// There are usually two values, the instance name and the class name
+pub(crate) fn get_wm_class(conn: &RustConnection, win_id: Window) -> Result<Vec<String>> {
+    let class_names = conn
+        .get_property(false, win_id, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+        .reply()?
+        .value;
+
+    Ok(decode_class(&class_names))
+}
+
+/// Split WM_CLASS in its parts
+fn decode_class(value: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(value)
+        .trim_matches('\0')
+        .split('\0')
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Get the title of the given window, from _NET_WM_NAME or else from the older WM_NAME
+pub(crate) fn get_wm_name(conn: &RustConnection, atoms: &Atoms, win_id: Window) -> Result<String> {
+    let net_wm_name = conn
+        .get_property(
+            false,
+            win_id,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            0,
+            1024,
+        )?
+        .reply()?;
+    if net_wm_name.value_len > 0 {
+        return Ok(String::from_utf8_lossy(&net_wm_name.value).into_owned());
+    }
+
+    let wm_name = conn
+        .get_property(false, win_id, AtomEnum::WM_NAME, AtomEnum::ANY, 0, 1024)?
+        .reply()?
+        .value;
+    Ok(decode_text(wm_name))
+}
+
+/// The parts of WM_CLASS and the title of a window
+pub(crate) type ClassAndTitle = (Vec<String>, String);
+
+/// The WM_CLASS and title of every window, sending all the requests before waiting for any reply.
+/// Windows that can't be asked (ex they went away in the meantime) are `None`
+pub(crate) fn get_classes_and_titles(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    windows: &[Window],
+) -> Result<Vec<Option<ClassAndTitle>>> {
+    let mut cookies = Vec::with_capacity(windows.len());
+    for &win_id in windows {
+        cookies.push((
+            conn.get_property(false, win_id, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?,
+            conn.get_property(
+                false,
+                win_id,
+                atoms._NET_WM_NAME,
+                atoms.UTF8_STRING,
+                0,
+                1024,
+            )?,
+            conn.get_property(false, win_id, AtomEnum::WM_NAME, AtomEnum::ANY, 0, 1024)?,
+        ));
+    }
+
+    Ok(cookies
+        .into_iter()
+        .map(|(class, net_wm_name, wm_name)| {
+            let class = decode_class(&class.reply().ok()?.value);
+            let net_wm_name = net_wm_name.reply().ok()?;
+            let wm_name = wm_name.reply().ok()?;
+            let title = if net_wm_name.value_len > 0 {
+                String::from_utf8_lossy(&net_wm_name.value).into_owned()
+            } else {
+                decode_text(wm_name.value)
+            };
+            Some((class, title))
+        })
+        .collect())
+}
+
+/// Text properties are meant to be Latin-1, but plenty of clients put UTF-8 in them.
+/// Anything that's not valid UTF-8 is read as Latin-1, which can't fail
+pub(crate) fn decode_text(value: Vec<u8>) -> String {
+    String::from_utf8(value).unwrap_or_else(|e| e.into_bytes().iter().map(|&b| b as char).collect())
+}
+
+/// Get the WM_WINDOW_ROLE of the given window, empty if it has none
+pub(crate) fn get_window_role(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    win_id: Window,
+) -> Result<String> {
+    let role = conn
+        .get_property(
+            false,
+            win_id,
+            atoms.WM_WINDOW_ROLE,
+            AtomEnum::STRING,
+            0,
+            1024,
+        )?
+        .reply()?
+        .value;
+
+    Ok(String::from_utf8_lossy(&role)
+        .trim_end_matches('\0')
+        .to_string())
+}
+
+/// Get the _NET_WM_WINDOW_TYPE of the given window, lowercase and without the prefix (ex `dialog`).
+/// Windows without one are `normal`, or `dialog` if they are transient, as EWMH says
+pub(crate) fn get_window_types(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    win_id: Window,
+) -> Result<Vec<String>> {
+    let reply = conn
+        .get_property(
+            false,
+            win_id,
+            atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            0,
+            32,
+        )?
+        .reply()?;
+    let types = reply.value32().into_iter().flatten().collect::<Vec<_>>();
+    if types.is_empty() {
+        let kind = if get_transient_for(conn, win_id)?.is_some() {
+            "dialog"
+        } else {
+            "normal"
+        };
+        return Ok(vec![kind.to_string()]);
+    }
+
+    let mut cookies = Vec::with_capacity(types.len());
+    for atom in types {
+        cookies.push(conn.get_atom_name(atom)?);
+    }
+    let mut names = Vec::with_capacity(cookies.len());
+    for cookie in cookies {
+        let name = String::from_utf8_lossy(&cookie.reply()?.name).to_ascii_lowercase();
+        names.push(name.trim_start_matches("_net_wm_window_type_").to_string());
+    }
+    Ok(names)
+}
+
+/// Get everything spawn rules match against
+pub(crate) fn get_window_props(
+    conn: &RustConnection,
+    atoms: &Atoms,
+    win_id: Window,
+) -> Result<WindowProps> {
+    let mut class = get_wm_class(conn, win_id)?.into_iter();
+    let instance = class.next().unwrap_or_default();
+    // Some windows only set one value
+    let class = class.next().unwrap_or_else(|| instance.clone());
+
+    Ok(WindowProps {
+        instance,
+        class,
+        title: get_wm_name(conn, atoms, win_id)?,
+        role: get_window_role(conn, atoms, win_id)?,
+        window_types: get_window_types(conn, atoms, win_id)?,
+    })
+}
+
+/// If the window asks for attention with the urgency flag of its WM_HINTS
+pub(crate) fn is_urgent(conn: &RustConnection, win_id: Window) -> Result<bool> {
+    let reply = conn
+        .get_property(false, win_id, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 9)?
+        .reply()?;
+    if reply.value_len == 0 {
+        // Not every window sets the hints
+        return Ok(false);
+    }
+
+    Ok(WmHints::from_reply(&reply)?.urgent)
+}
+
 pub(crate) fn grab_buttons(
     conn: &RustConnection,
     window: Window,
@@ -134,14 +464,26 @@ pub(crate) fn get_monitors(
     let screen = &conn.setup().roots[screen_num];
     // Todo: Should check randr version? https://github.com/linebender/druid/pull/1804/files#diff-887fa9b9ca679a0017ce71c83d9be00af81b30d94c20f2430ffac6caa2fc3531R74
 
-    Ok(randr::get_monitors(conn, screen.root, true)
+    let monitors = randr::get_monitors(conn, screen.root, true)
         .context("Failed to get monitors from randr")?
         .reply()
         .context("Failed to get monitors from randr")?
-        .monitors
-        .iter()
-        .map(|info| Monitor::new(config, Rect::new(info.x, info.y, info.width, info.height)))
-        .collect())
+        .monitors;
+
+    // Bunch request the names of the monitors
+    let mut cookies = Vec::with_capacity(monitors.len());
+    for info in monitors.iter() {
+        cookies.push(conn.get_atom_name(info.name)?);
+    }
+
+    let mut result = Vec::with_capacity(monitors.len());
+    for (info, cookie) in monitors.iter().zip(cookies) {
+        let name = String::from_utf8_lossy(&cookie.reply()?.name).into_owned();
+        let rect = Rect::new(info.x, info.y, info.width, info.height);
+        result.push(Monitor::new(config, name, rect));
+    }
+
+    Ok(result)
 }
 
 pub(crate) fn expand_tilde(path: &Path) -> PathBuf {
@@ -176,7 +518,7 @@ pub(crate) fn init_logging(log_dir: &Option<PathBuf>) -> Result<LoggerHandle, Fl
             style(level).paint(level.to_string()),
             record.file().unwrap_or("<unnamed>"),
             record.line().unwrap_or(0),
-            style(level).paint(&record.args().to_string())
+            style(level).paint(record.args().to_string())
         )
     }
 
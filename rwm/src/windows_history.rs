@@ -3,10 +3,11 @@ use std::{cmp::Ordering, collections::VecDeque};
 use x11rb::protocol::xproto::Window;
 
 use crate::{
+    rect::Rect,
     states::{TagState, WinState},
     utils,
 };
-use common::{Direction, TagId};
+use common::{Direction, SpatialDirection, TagId};
 
 /// A wrapper around a VecDequeue.
 /// Currently there is no way to keep a history when switching tags so
@@ -58,6 +59,11 @@ impl WindowsHistory {
         }
     }
 
+    /// Move all the windows of `other` to the back of the history, keeping the current focus
+    pub(crate) fn append(&mut self, other: WindowsHistory) {
+        self.windows.extend(other.windows);
+    }
+
     /// "Forget" a window returning it and the next window that should get focus
     /// Warning: self.cur points to None after this
     pub(crate) fn forget(
@@ -168,6 +174,81 @@ impl WindowsHistory {
             .or_else(|| take_all_rev().find(|(_, win)| utils::is_visible(win, tags)))
     }
 
+    /// Find the visible window that is closest to `rect` in the given direction
+    pub(crate) fn find_closest(
+        &self,
+        rect: &Rect,
+        dir: SpatialDirection,
+        tags: &[TagState],
+    ) -> Option<(usize, &WinState)> {
+        let visible = self
+            .windows
+            .iter()
+            .enumerate()
+            .filter(|(_, win)| utils::is_visible(win, tags))
+            .collect::<Vec<_>>();
+        let rects = visible
+            .iter()
+            .map(|(_, win)| win.rect())
+            .collect::<Vec<_>>();
+
+        rect.closest(dir, &rects).map(|i| visible[i])
+    }
+
+    /// Get a mutable reference to the window in the given position
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut WinState> {
+        self.windows.get_mut(index)
+    }
+
+    /// Swap the current window with the visible window in the given position, focus follows.
+    /// Returns if they were swapped
+    pub(crate) fn swap_focused(&mut self, index: usize, tags: &[TagState]) -> bool {
+        let cur = match self.cur {
+            Some(cur) if cur != index => cur,
+            _ => return false,
+        };
+        if !self
+            .windows
+            .get(index)
+            .is_some_and(|win| utils::is_visible(win, tags))
+        {
+            return false;
+        }
+        self.windows.swap(cur, index);
+        self.cur = Some(index);
+        true
+    }
+
+    /// Swap the current window with the master (the first visible tiled window),
+    /// or with the next tiled window if it's already the master.
+    /// Returns the window that ends up as master, which should get focus
+    pub(crate) fn zoom(&mut self, tags: &[TagState]) -> Option<Window> {
+        let cur = self.cur?;
+        if !self.windows[cur].is_tiled() {
+            return None;
+        }
+
+        let mut tiled = self
+            .windows
+            .iter()
+            .enumerate()
+            .filter(|(_, win)| win.is_tiled() && utils::is_visible(win, tags))
+            .map(|(i, _)| i);
+        let master = tiled.next()?;
+
+        if master == cur {
+            let next = tiled.next()?;
+            self.windows.swap(master, next);
+            // Focus stays with the old master until the new one is focused
+            self.cur = Some(next);
+        } else {
+            self.windows.swap(master, cur);
+            self.cur = Some(master);
+        }
+
+        Some(self.windows[master].id)
+    }
+
     /// Move the current window up or down, focus doesn't follow
     pub(crate) fn shift(&mut self, dir: Direction, tags: &[TagState]) {
         if let Some(cur) = self.cur {